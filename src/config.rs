@@ -0,0 +1,179 @@
+use std::{collections::{HashMap, HashSet}, fs, hash::Hash, path::PathBuf};
+use anyhow::{Result, Context};
+use itertools::Itertools;
+use crate::idiom::{MarkdownRules, Scoring, Stemmer, UrlRule};
+use serde::{Deserialize, Serialize};
+use serenity::model::{prelude::{ChannelId, GuildId, Message, MessageType, UserId, WebhookId}, Timestamp};
+const READ_PAST: u64 = 10_000;
+const DAYS: i64 = 100;
+const HALF_LIFE: u32 = 30;
+const EMOJI_RETENTION: u32 = 365;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct GuildConfig {
+    /// How many past messages to read in each channel
+    pub read_past: u64,
+    /// Don't read past messages older than this many days
+    pub days: i64,
+    /// Messages lose half their weight every this many days, 0 to never lose weight
+    pub half_life: u32,
+    /// If not empty, only these channels (or channels in these categories) are read
+    pub allow_channels: HashSet<ChannelId>,
+    /// These channels (or channels in these categories) are never read
+    pub deny_channels: HashSet<ChannelId>,
+    /// Messages from bots and webhooks are ignored, except for these bots
+    pub allow_bots: HashSet<UserId>,
+    /// and these webhooks, whose messages each have the author they were sent with
+    pub allow_webhooks: HashSet<WebhookId>,
+    /// Words to ignore on top of the built-in stopwords, in lowercase
+    pub stopwords: HashSet<String>,
+    pub markdown: MarkdownRules,
+    /// Shorten letters repeated 3 times or more to 2, so that "nooooo" and "nooo" are the same word
+    pub collapse_repeats: bool,
+    /// Merge the inflections of words ("run", "runs", "running") in this language
+    pub stemmer: Option<Stemmer>,
+    /// How the words of a cloud are weighted
+    pub scoring: Scoring,
+    /// Emoji uses are forgotten after this many days, 0 to keep them forever
+    pub emoji_retention: u32,
+}
+
+impl Default for GuildConfig {
+    fn default() -> Self {
+        Self {
+            read_past: READ_PAST,
+            days: DAYS,
+            half_life: HALF_LIFE,
+            allow_channels: HashSet::new(),
+            deny_channels: HashSet::new(),
+            allow_bots: HashSet::new(),
+            allow_webhooks: HashSet::new(),
+            stopwords: HashSet::new(),
+            markdown: MarkdownRules::default(),
+            collapse_repeats: true,
+            stemmer: None,
+            scoring: Scoring::LogOdds,
+            emoji_retention: EMOJI_RETENTION,
+        }
+    }
+}
+
+/// Inserts the value if it's absent, removes it otherwise
+pub fn toggle<T: Hash + Eq>(set: &mut HashSet<T>, value: T) {
+    if !set.remove(&value) {
+        set.insert(value);
+    }
+}
+
+fn channels_msg(channels: &HashSet<ChannelId>, if_empty: &str) -> String {
+    if channels.is_empty() {
+        if_empty.to_string()
+    } else {
+        channels.iter().map(|channel_id| format!("<#{}>", channel_id)).join(", ")
+    }
+}
+
+impl GuildConfig {
+    pub fn msg(&self) -> String {
+        format!(
+            "Past messages read per channel: {}\nMax age of past messages: {} days\nHalf-life of messages: {}\nOnly read: {}\nNever read: {}\nBots read: {}\nWebhooks read: {}\nCustom stopwords: {}\nLinks: {}\nIgnore code: {}\nIgnore quotes: {}\nIgnore spoilers: {}\nShorten repeated letters: {}\nMerge word forms: {}\nWord weights: {}\nEmoji uses kept for: {}",
+            self.read_past, self.days, 
+            self.half_life().map_or("none".to_string(), |days| format!("{} days", days)),
+            channels_msg(&self.allow_channels, "all channels"), 
+            channels_msg(&self.deny_channels, "no channels"),
+            if self.allow_bots.is_empty() {
+                "none".to_string()
+            } else {
+                self.allow_bots.iter().map(|user_id| format!("<@{}>", user_id)).join(", ")
+            },
+            if self.allow_webhooks.is_empty() {
+                "none".to_string()
+            } else {
+                self.allow_webhooks.iter().join(", ")
+            },
+            if self.stopwords.is_empty() {
+                "none".to_string()
+            } else {
+                self.stopwords.iter().sorted().join(", ")
+            },
+            match self.markdown.urls {
+                UrlRule::Keep => "kept",
+                UrlRule::Domain => "only the domain is kept",
+                UrlRule::Drop => "ignored",
+            },
+            self.markdown.skip_code, self.markdown.skip_quotes, self.markdown.skip_spoilers,
+            self.collapse_repeats,
+            self.stemmer.map_or("no", |stemmer| stemmer.name()),
+            self.scoring.name(),
+            if self.emoji_retention == 0 { "ever".to_string() } else { format!("{} days", self.emoji_retention) }
+        )
+    }
+
+    /// In days
+    pub fn half_life(&self) -> Option<f32> {
+        (self.half_life > 0).then_some(self.half_life as f32)
+    }
+
+    /// Unix timestamp before which emoji uses are forgotten, if they ever are
+    pub fn emoji_cutoff(&self) -> Option<i64> {
+        (self.emoji_retention > 0).then(|| Timestamp::now().unix_timestamp() - 3600 * 24 * self.emoji_retention as i64)
+    }
+
+    pub fn cutoff_date(&self) -> Timestamp {
+        // days may come from a hand-edited config file
+        Timestamp::from_unix_timestamp(Timestamp::now().unix_timestamp().saturating_sub(self.days.saturating_mul(3600 * 24)))
+            .unwrap_or(Timestamp::from_unix_timestamp(0).unwrap())
+    }
+
+    /// Ignores system messages, and messages from bots and webhooks unless they're allowed
+    pub fn should_read_message(&self, message: &Message) -> bool {
+        matches!(message.kind, MessageType::Regular | MessageType::InlineReply)
+            && match message.webhook_id {
+                Some(webhook_id) => self.allow_webhooks.contains(&webhook_id),
+                None => !message.author.bot || self.allow_bots.contains(&message.author.id)
+            }
+    }
+
+    /// Takes the channel followed by its ancestors, 
+    /// since threads follow the settings of their channel and channels the settings of their category
+    pub fn should_read(&self, lineage: &[ChannelId]) -> bool {
+        (self.allow_channels.is_empty() || lineage.iter().any(|id| self.allow_channels.contains(id)))
+            && !lineage.iter().any(|id| self.deny_channels.contains(id))
+    }
+}
+
+#[derive(Serialize, Deserialize, Default)]
+struct ConfigFile {
+    #[serde(default)]
+    guilds: HashMap<GuildId, GuildConfig>,
+}
+
+/// The config file, which can be edited by hand or with the /wordy config command
+pub struct ConfigStore {
+    path: PathBuf,
+}
+
+impl ConfigStore {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    pub fn load(&self) -> Result<HashMap<GuildId, GuildConfig>> {
+        if !self.path.exists() {
+            return Ok(HashMap::new());
+        }
+        let content = fs::read_to_string(&self.path)?;
+        let config: ConfigFile = toml::from_str(&content).context("Invalid config file")?;
+        Ok(config.guilds)
+    }
+
+    pub fn save(&self, guilds: HashMap<GuildId, GuildConfig>) -> Result<()> {
+        let content = toml::to_string_pretty(&ConfigFile { guilds })?;
+        // write to a temporary file first so a crash mid-write doesn't corrupt the previous config
+        let tmp_path = self.path.with_extension("toml.tmp");
+        fs::write(&tmp_path, content)?;
+        fs::rename(&tmp_path, &self.path)?;
+        Ok(())
+    }
+}
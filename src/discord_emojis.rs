@@ -2,6 +2,7 @@ use image::DynamicImage;
 use moka::sync::Cache;
 use reqwest::get;
 use anyhow::Result;
+use itertools::Itertools;
 
 pub struct DiscordEmojis {
     emojis: Cache<String, DynamicImage>
@@ -14,15 +15,31 @@ impl DiscordEmojis {
         }
     }
 
-    pub async fn get(&self, id: &str) -> Result<DynamicImage> {
-        if let Some(img) = self.emojis.get(id) {
+    async fn fetch(&self, key: &str, url: String) -> Result<DynamicImage> {
+        if let Some(img) = self.emojis.get(key) {
             Ok(img.clone())
         } else {
-            let url = format!("https://cdn.discordapp.com/emojis/{}.webp", id);
-            let img_bytes = get(url).await?.bytes().await?;
+            let img_bytes = get(url).await?.error_for_status()?.bytes().await?;
             let image = image::load_from_memory(&img_bytes)?;
-            self.emojis.insert(id.to_string(), image.clone());
+            self.emojis.insert(key.to_string(), image.clone());
             Ok(image)
         }
     }
+
+    pub async fn get(&self, id: &str) -> Result<DynamicImage> {
+        self.fetch(id, format!("https://cdn.discordapp.com/emojis/{}.webp", id)).await
+    }
+
+    /// Standard emojis are rendered with Twemoji, which is what Discord uses
+    pub async fn get_unicode(&self, emoji: &str) -> Result<DynamicImage> {
+        // Twemoji files are named after the code points, without the variation selector unless it's a ZWJ sequence
+        let code_points = emoji.chars()
+            .filter(|c| emoji.contains('\u{200D}') || *c != '\u{FE0F}')
+            .map(|c| format!("{:x}", c as u32))
+            .join("-");
+        self.fetch(
+            &code_points, 
+            format!("https://cdn.jsdelivr.net/gh/jdecked/twemoji@latest/assets/72x72/{}.png", code_points)
+        ).await
+    }
 }
@@ -1,11 +1,16 @@
 use std::sync::Arc;
 use serenity::{
-    all::{CommandInteraction, CreateAttachment, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, GetMessages, Permissions}, async_trait, http::Http, model:: {
-        prelude::{GuildChannel, Message}, Timestamp,
+    all::{CommandInteraction, CreateAllowedMentions, CreateAttachment, CreateInteractionResponse, CreateInteractionResponseFollowup, CreateInteractionResponseMessage, GetMessages, Permissions}, 
+    async_trait, cache::Cache, http::{Http, LightMethod, Request, Route}, model:: {
+        prelude::{ChannelId, ChannelType, GuildChannel, GuildId, Message, MessageId, ThreadsData}, Timestamp,
     }, prelude::*
 };
 use anyhow::{Result, Context as ContextErr};
+use log::{trace, warn};
 const DISCORD_READ_LIMIT: u64 = 100;
+const DISCORD_THREADS_LIMIT: u64 = 100;
+// Milliseconds between the Unix epoch and the first second of 2015, Discord's epoch
+const DISCORD_EPOCH: i64 = 1_420_070_400_000;
 
 type Command = CommandInteraction;
 pub struct Attachment { pub file: Vec<u8>, pub filename: String }
@@ -13,9 +18,13 @@ pub struct Attachment { pub file: Vec<u8>, pub filename: String }
 
 #[async_trait]
 pub trait Bot {
+    /// Answers the command, the mentions in the content don't ping anyone
     async fn answer(&self, command: &Command, content: &str, files: Vec<Attachment>) -> Result<()>;
 
     async fn followup(&self, command: &Command, content: &str, files: Vec<Attachment>) -> Result<()>;
+
+    /// Answers with a message that only the user of the command can see
+    async fn whisper(&self, command: &Command, content: &str) -> Result<()>;
 }
 
 #[async_trait]
@@ -27,6 +36,7 @@ impl Bot for Http {
                 CreateInteractionResponse::Message(
                     CreateInteractionResponseMessage::new()
                         .content(content)
+                        .allowed_mentions(CreateAllowedMentions::new())
                         .add_files(files.into_iter().map(|a| CreateAttachment::bytes(a.file, a.filename)))
                 )).await
         ).context("Command create response failed")
@@ -38,11 +48,24 @@ impl Bot for Http {
             .create_followup(self, 
                 CreateInteractionResponseFollowup::new()
                     .content(content)
+                    .allowed_mentions(CreateAllowedMentions::new())
                     .add_files(files.into_iter().map(|a| CreateAttachment::bytes(a.file, a.filename)))
             ).await
         ).context("Command create followup failed")?;
         Ok(())
     }
+
+    async fn whisper(&self, command: &Command, content: &str) -> Result<()> {
+        (
+            command
+            .create_response(self, 
+                CreateInteractionResponse::Message(
+                    CreateInteractionResponseMessage::new()
+                        .content(content)
+                        .ephemeral(true)
+                )).await
+        ).context("Command create response failed")
+    }
 }
 
 pub struct ReadResult {
@@ -75,7 +98,7 @@ pub async fn read_past(http: &Arc<Http>, channel: &GuildChannel, limit: u64, cut
         };
         // This really should be hanlded by serenity
         // https://serenity-rs.github.io/serenity/current/serenity/model/channel/struct.GuildChannel.html#method.messages
-        if messages.len() == 0 {
+        if messages.is_empty() {
             return ReadResult {
                 messages: res,
                 result: Err(SerenityError::Model(ModelError::InvalidPermissions { 
@@ -100,3 +123,149 @@ pub async fn read_past(http: &Arc<Http>, channel: &GuildChannel, limit: u64, cut
         result: Ok(()),
     }
 }
+
+/// The smallest message id that could have been sent at this date
+fn snowflake_at(date: Timestamp) -> MessageId {
+    let millis = (date.unix_timestamp()*1000 - DISCORD_EPOCH).max(1);
+    MessageId::new((millis as u64) << 22)
+}
+
+pub async fn read_after(
+    http: &Arc<Http>, channel: &GuildChannel, after: MessageId, limit: u64, cutoff_date: Timestamp
+) -> ReadResult {
+    // Same as read_past but going forward in time, starting after the message we last read.
+    // Messages are returned from oldest to newest.
+    let mut res: Vec<Message> = Vec::new();
+    let mut last = after.max(snowflake_at(cutoff_date));
+    let mut remaining = limit;
+    while remaining > 0 {
+        let batch_size = remaining.min(DISCORD_READ_LIMIT);
+        let mut messages = match channel.messages(
+            &http, GetMessages::new().after(last).limit(batch_size as u8)
+        ).await {
+            Ok(messages) => messages,
+            Err(e) => {
+                return ReadResult {
+                    messages: res,
+                    result: Err(e),
+                };
+            }
+        };
+        // Discord doesn't guarantee any order so we sort them ourselves
+        messages.sort_by_key(|message| message.id);
+        let len = messages.len() as u64;
+        if let Some(message) = messages.last() {
+            last = message.id;
+        }
+        res.extend(messages);
+        if len < batch_size {
+            // we caught up to the present
+            break;
+        }
+        remaining = limit - (res.len() as u64).min(limit);
+        if remaining == 0 {
+            warn!(
+                target: "wordy", "Reached the limit of {} messages in {} before catching up, the rest will be read on the next catch-up", 
+                limit, channel.name()
+            );
+        }
+    }
+    ReadResult {
+        messages: res,
+        result: Ok(()),
+    }
+}
+
+/// Whether we can read messages from this channel (threads, text chats of voice channels, etc.)
+pub fn is_readable(channel: &GuildChannel) -> bool {
+    matches!(channel.kind, 
+        ChannelType::Text | ChannelType::News | ChannelType::Voice | ChannelType::Stage 
+        | ChannelType::NewsThread | ChannelType::PublicThread | ChannelType::PrivateThread
+    )
+}
+
+/// The channel followed by its ancestors (the channel of a thread, the category of a channel)
+pub fn lineage(channel_id: ChannelId, parent: impl Fn(ChannelId) -> Option<ChannelId>) -> Vec<ChannelId> {
+    let mut res = vec![channel_id];
+    while let Some(parent_id) = parent(res[res.len()-1]) {
+        if res.contains(&parent_id) {
+            break;
+        }
+        res.push(parent_id);
+    }
+    res
+}
+
+/// Same as lineage, using the cache to find the parents
+pub fn cached_lineage(cache: &Cache, guild_id: GuildId, channel_id: ChannelId) -> Vec<ChannelId> {
+    let Some(guild) = cache.guild(guild_id) else {
+        return vec![channel_id];
+    };
+    lineage(channel_id, |id| guild.channels.get(&id)
+        .or_else(|| guild.threads.iter().find(|thread| thread.id == id))
+        .and_then(|channel| channel.parent_id)
+    )
+}
+
+/// Whether this channel can contain threads (forum posts are threads too)
+fn has_threads(channel: &GuildChannel) -> bool {
+    matches!(channel.kind, ChannelType::Text | ChannelType::News | ChannelType::Forum)
+}
+
+async fn archived_threads(
+    http: &Arc<Http>, channel_id: ChannelId, private: bool, cutoff_date: Timestamp
+) -> Result<Vec<GuildChannel>, SerenityError> {
+    // serenity's get_archived_*_threads expects a number for `before` but Discord wants a timestamp, 
+    // so we make the request ourselves
+    let mut res: Vec<GuildChannel> = Vec::new();
+    loop {
+        let mut params = vec![("limit", DISCORD_THREADS_LIMIT.to_string())];
+        if let Some(before) = res.last().and_then(|thread| thread.thread_metadata?.archive_timestamp) {
+            params.push(("before", before.to_string()));
+        }
+        let route = if private {
+            Route::ChannelArchivedPrivateThreads { channel_id }
+        } else {
+            Route::ChannelArchivedPublicThreads { channel_id }
+        };
+        let threads_data: ThreadsData = http.fire(
+            Request::new(route, LightMethod::Get).params(Some(params))
+        ).await?;
+        // threads are sorted from most to least recently archived
+        let len = res.len();
+        res.extend(threads_data.threads.into_iter().take_while(|thread| 
+            thread.thread_metadata
+                .and_then(|metadata| metadata.archive_timestamp)
+                .is_some_and(|archived| archived >= cutoff_date)
+        ));
+        if !threads_data.has_more || res.len() - len < DISCORD_THREADS_LIMIT as usize {
+            return Ok(res);
+        }
+    }
+}
+
+/// All the threads of the guild that were active after cutoff_date, 
+/// including forum posts and the private threads we're allowed to see
+pub async fn read_threads<'a>(
+    http: &Arc<Http>, guild_id: GuildId, channels: impl Iterator<Item = &'a GuildChannel>, cutoff_date: Timestamp
+) -> Vec<GuildChannel> {
+    let mut res = match guild_id.get_active_threads(http).await {
+        Ok(threads_data) => threads_data.threads,
+        Err(why) => {
+            warn!(target: "wordy", "Couldn't get the active threads of Guild {}: {}", guild_id, why);
+            Vec::new()
+        }
+    };
+    for channel in channels.filter(|channel| has_threads(channel)) {
+        match archived_threads(http, channel.id, false, cutoff_date).await {
+            Ok(threads) => res.extend(threads),
+            Err(why) => warn!(target: "wordy", "Couldn't get the archived threads of {}: {}", channel.name, why)
+        }
+        // this requires the Manage Threads permission, which we usually don't have
+        match archived_threads(http, channel.id, true, cutoff_date).await {
+            Ok(threads) => res.extend(threads),
+            Err(why) => trace!(target: "wordy", "Couldn't get the private archived threads of {}: {}", channel.name, why)
+        }
+    }
+    res
+}
@@ -1,36 +1,91 @@
 use itertools::Itertools;
 use leaderboard::{Ranking, View, Sections};
-use serenity::model::prelude::Emoji;
+use serenity::model::prelude::{Emoji, EmojiId, Sticker};
 const TOP_EMO: usize = 5;
 const MAX_EMO_GROUP: usize = 15;
+// the report has to fit in a message of at most MAX_MSG_LEN chars, the CSV has everything
+const MAX_REPORT_UNUSED: usize = 20;
+const MAX_REPORT_ENTRIES: usize = 10;
+const MAX_MSG_LEN: usize = 2000;
 
-pub struct EmojiUsage(pub Emoji, pub f64);
+/// Which uses of the emojis to count
+#[derive(Clone, Copy, PartialEq)]
+pub enum EmojiSource {
+    /// Emojis in the text of the messages
+    Messages,
+    /// Reactions to the messages
+    Reactions,
+    Both,
+}
 
-impl PartialEq for EmojiUsage {
-    fn eq(&self, other: &Self) -> bool {
-        (self.1*100.).round() == (other.1*100.).round()
+impl EmojiSource {
+    pub const ALL: [EmojiSource; 3] = [EmojiSource::Messages, EmojiSource::Reactions, EmojiSource::Both];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EmojiSource::Messages => "messages",
+            EmojiSource::Reactions => "reactions",
+            EmojiSource::Both => "both",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|source| source.name() == name)
     }
+}
+
+/// An emoji (or sticker), how many times it was used and which share of the uses it represents
+pub struct EmojiUsage<T = Emoji>(pub T, pub u32, pub f64);
 
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
+pub type StickerUsage = EmojiUsage<Sticker>;
+
+impl<T> PartialEq for EmojiUsage<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.1 == other.1
     }
 }
 
-impl PartialOrd for EmojiUsage {
+impl<T> PartialOrd for EmojiUsage<T> {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
         self.1.partial_cmp(&other.1)
     }
 }
 
-pub fn emo_entry_msg(rank: usize, freq: f64, emos: Vec<&Emoji>) -> String {
+pub fn emo_entry_msg(rank: usize, count: u32, freq: f64, emos: Vec<&Emoji>) -> String {
     // limit to 20 emojis because the message gets too long otherwise
     let ellipsis = if emos.len() > MAX_EMO_GROUP { "… " } else { "" };
     let emo_str = emos.into_iter().take(MAX_EMO_GROUP).join("");
-    format!("{}. {}{}: {:.0}%", rank, emo_str, ellipsis, freq*100.0)
+    format!("{}. {}{}: {} ({:.0}%)", rank, emo_str, ellipsis, count, freq*100.0)
+}
+
+/// Stickers can't be shown inline, so they are listed by name
+pub fn sticker_entry_msg(rank: usize, count: u32, freq: f64, stickers: Vec<&Sticker>) -> String {
+    let ellipsis = if stickers.len() > MAX_EMO_GROUP { ", …" } else { "" };
+    let names = stickers.into_iter().take(MAX_EMO_GROUP).map(|sticker| &sticker.name).join(", ");
+    format!("{}. {}{}: {} ({:.0}%)", rank, names, ellipsis, count, freq*100.0)
 }
 
 pub fn emo_ranking_msg(emo_ranking: Vec<EmojiUsage>) -> String {
-    if emo_ranking.len() == 0 {
+    ranking_msg(emo_ranking, emo_entry_msg)
+}
+
+pub fn sticker_ranking_msg(sticker_ranking: Vec<StickerUsage>) -> String {
+    ranking_msg(sticker_ranking, sticker_entry_msg)
+}
+
+/// The top users and how many times they used something, out of a total that includes the other users
+pub fn user_ranking_msg(ranking: Vec<(String, u32)>, total: u32) -> String {
+    if ranking.is_empty() {
+        return "No entries :(".to_string();
+    }
+    ranking.into_iter().enumerate()
+        .map(|(i, (name, count))| format!("{}. {}: {} ({:.0}%)", i + 1, name, count, count as f64/total.max(1) as f64*100.0))
+        .join("\n")
+}
+
+/// The top entries and the last group, with the entries of a same rank grouped on one line
+fn ranking_msg<T>(emo_ranking: Vec<EmojiUsage<T>>, entry_msg: impl Fn(usize, u32, f64, Vec<&T>) -> String) -> String {
+    if emo_ranking.is_empty() {
         return "No entries :(".to_string();
     }
     let grouped_ranking = emo_ranking.iter_ranked().collect_vec();
@@ -39,12 +94,12 @@ pub fn emo_ranking_msg(emo_ranking: Vec<EmojiUsage>) -> String {
     grouped_ranking.iter_sections(vec![0..TOP_EMO, (len-1)..len]).map(|view|
         match view {
             View::Item(emo_usages) => {
-                let emos = emo_usages.into_iter().map(
-                    |EmojiUsage(emo, _usage)| emo
+                let emos = emo_usages.iter().map(
+                    |EmojiUsage(emo, _count, _freq)| emo
                 ).collect_vec();
-                let freq = emo_usages[0].1;
+                let EmojiUsage(_, count, freq) = emo_usages[0];
                 rank += 1;
-                emo_entry_msg(rank, freq, emos)
+                entry_msg(rank, count, freq, emos)
             }
             View::Skipped(count) => {
                 rank += count;
@@ -53,3 +108,90 @@ pub fn emo_ranking_msg(emo_ranking: Vec<EmojiUsage>) -> String {
         }
     ).join("\n")
 }
+
+/// An emoji of the cleanup report, with its uses in the period and in the one before
+pub struct ReportEntry {
+    pub name: String,
+    pub id: EmojiId,
+    pub animated: bool,
+    pub uses: u32,
+    pub previous_uses: u32,
+}
+
+impl ReportEntry {
+    pub fn markup(&self) -> String {
+        format!("<{}:{}:{}>", if self.animated { "a" } else { "" }, self.name, self.id)
+    }
+
+    pub fn url(&self) -> String {
+        format!("https://cdn.discordapp.com/emojis/{}.{}", self.id, if self.animated { "gif" } else { "png" })
+    }
+}
+
+/// What admins need to know to pick which emojis to remove or to add
+pub struct EmojiReport {
+    pub days: i64,
+    /// Longer than days if the emoji retention was too short to compare them with the ones before
+    pub requested_days: i64,
+    /// In days, 0 if emoji uses are kept forever
+    pub retention: i64,
+    /// Server emojis nobody used in the period
+    pub unused: Vec<ReportEntry>,
+    /// Server emojis used less than in the previous period, the steepest drops first
+    pub declining: Vec<ReportEntry>,
+    /// Emojis from other servers, the most used first
+    pub external: Vec<ReportEntry>,
+}
+
+pub fn report_msg(report: &EmojiReport) -> String {
+    let mut lines = Vec::new();
+    if report.days < report.requested_days {
+        lines.push(format!(
+            "Emoji uses are only kept for {} days, so the report is shortened to compare the last {} days with the ones before.",
+            report.retention, report.days
+        ));
+    }
+    lines.push(format!("Emoji report for the last {} days:", report.days));
+    if report.unused.is_empty() {
+        lines.push("**Unused**: none".to_string());
+    } else {
+        let ellipsis = if report.unused.len() > MAX_REPORT_UNUSED { "…" } else { "" };
+        let emos = report.unused.iter().take(MAX_REPORT_UNUSED).map(ReportEntry::markup).join("");
+        lines.push(format!("**Unused** ({}): {}{}", report.unused.len(), emos, ellipsis));
+    }
+    if !report.declining.is_empty() {
+        lines.push(format!("**Declining** (compared to the {} days before):", report.days));
+        lines.extend(report.declining.iter().take(MAX_REPORT_ENTRIES).map(|entry|
+            format!("{}: {} → {}", entry.markup(), entry.previous_uses, entry.uses)
+        ));
+    }
+    if !report.external.is_empty() {
+        lines.push("**Most used from other servers**:".to_string());
+        // the bot can't display emojis from servers it's not in, so they are linked instead
+        lines.extend(report.external.iter().take(MAX_REPORT_ENTRIES).map(|entry|
+            format!("[:{}:](<{}>): {}", entry.name, entry.url(), entry.uses)
+        ));
+    }
+    // long emoji names could still make it too long, in which case the last lines are dropped
+    let mut msg = String::new();
+    for line in lines {
+        if msg.chars().count() + line.chars().count() + 1 > MAX_MSG_LEN {
+            break;
+        }
+        msg += &line;
+        msg.push('\n');
+    }
+    msg
+}
+
+/// Every entry of the report, one per line, emoji names being only made of letters, digits and underscores
+pub fn report_csv(report: &EmojiReport) -> String {
+    let mut csv = "category,name,id,animated,uses,previous_uses,url\n".to_string();
+    [("unused", &report.unused), ("declining", &report.declining), ("external", &report.external)].into_iter()
+        .flat_map(|(category, entries)| entries.iter().map(move |entry| (category, entry)))
+        .for_each(|(category, entry)| csv += &format!(
+            "{},{},{},{},{},{},{}\n", 
+            category, entry.name, entry.id, entry.animated, entry.uses, entry.previous_uses, entry.url()
+        ));
+    csv
+}
@@ -0,0 +1,111 @@
+use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Serialize};
+use super::top_freqs::TopFreqs;
+const DAY: i64 = 3600 * 24;
+const MONTH: i64 = DAY * 30;
+// enough daily buckets for the last month, and monthly buckets for the last year
+const KEPT_DAYS: i64 = 30;
+const KEPT_MONTHS: i64 = 12;
+
+/// A time window to look at
+#[derive(Clone, Copy, PartialEq)]
+pub enum Period {
+    Week,
+    Month,
+    Year,
+    All,
+}
+
+impl Period {
+    pub const ALL: [Period; 4] = [Period::Week, Period::Month, Period::Year, Period::All];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Period::Week => "week",
+            Period::Month => "month",
+            Period::Year => "year",
+            Period::All => "all",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|period| period.name() == name)
+    }
+
+    /// Unix timestamp at which the window starts, None if it covers everything
+    pub fn start(&self, now: i64) -> Option<i64> {
+        match self {
+            Period::Week => Some(now - 7 * DAY),
+            Period::Month => Some(now - KEPT_DAYS * DAY),
+            Period::Year => Some(now - KEPT_MONTHS * MONTH),
+            Period::All => None,
+        }
+    }
+}
+
+/// Vocabularies over a day and over a month, merged to answer queries on longer windows.
+/// At most KEPT_DAYS + KEPT_MONTHS vocabularies are kept, older ones are dropped.
+#[derive(Serialize, Deserialize)]
+pub struct Buckets<const S: usize> {
+    days: BTreeMap<i64, TopFreqs<S>>,
+    months: BTreeMap<i64, TopFreqs<S>>,
+}
+
+fn prune<const S: usize>(buckets: &mut BTreeMap<i64, TopFreqs<S>>, oldest: i64) {
+    while buckets.first_key_value().is_some_and(|(bucket, _)| *bucket < oldest) {
+        buckets.pop_first();
+    }
+}
+
+impl<const S: usize> Buckets<S> {
+    pub fn new() -> Self {
+        Self { days: BTreeMap::new(), months: BTreeMap::new() }
+    }
+
+    /// Drops the buckets that are too old to be kept
+    pub fn prune(&mut self, now: i64) {
+        prune(&mut self.days, now / DAY - KEPT_DAYS);
+        prune(&mut self.months, now / MONTH - KEPT_MONTHS);
+    }
+
+    /// Messages too old for any bucket are ignored
+    pub fn add(&mut self, idx: usize, value: f32, time: i64, now: i64) {
+        self.prune(now);
+        if time / DAY >= now / DAY - KEPT_DAYS {
+            self.days.entry(time / DAY).or_insert(TopFreqs::new()).add(idx, value);
+        }
+        if time / MONTH >= now / MONTH - KEPT_MONTHS {
+            self.months.entry(time / MONTH).or_insert(TopFreqs::new()).add(idx, value);
+        }
+    }
+
+    pub fn remove(&mut self, idx: usize, value: f32, time: i64) {
+        if let Some(voc) = self.days.get_mut(&(time / DAY)) {
+            voc.remove(&idx, value);
+        }
+        if let Some(voc) = self.months.get_mut(&(time / MONTH)) {
+            voc.remove(&idx, value);
+        }
+    }
+
+    /// Every token in the buckets, possibly more than once
+    pub fn indices(&self) -> impl Iterator<Item = usize> + '_ {
+        self.days.values().chain(self.months.values())
+            .flat_map(|voc| voc.iter())
+            .map(|(idx, _)| idx)
+    }
+
+    /// The merged vocabulary of the buckets in the window, which mustn't be Period::All
+    pub fn window(&self, period: Period, now: i64) -> HashMap<usize, f32> {
+        let start = period.start(now).unwrap_or(0);
+        let vocs = if period == Period::Year {
+            self.months.range(start / MONTH..)
+        } else {
+            self.days.range(start / DAY..)
+        };
+        let mut merged = HashMap::new();
+        vocs.flat_map(|(_, voc)| voc.iter())
+            .for_each(|(idx, v)| *merged.entry(idx).or_default() += v);
+        merged
+    }
+}
@@ -1,54 +1,464 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::hash::Hash;
+use std::time::{SystemTime, UNIX_EPOCH};
 use itertools::Itertools;
 use bimap::BiMap;
+use serde::{Deserialize, Serialize};
 use super::top_freqs::TopFreqs;
+use super::buckets::{Buckets, Period};
+use super::scoring::{log_odds, tf_idf, Scoring};
+use super::text_utils::GAP;
 const PLACE_VOC_LEN: usize = 500;
 const PERSON_VOC_LEN: usize = 200;
+const PLACE_PHRASES_LEN: usize = 500;
+const PERSON_PHRASES_LEN: usize = 200;
+// we look for phrases of 2 to MAX_PHRASE_LEN words
+const MAX_PHRASE_LEN: usize = 3;
+// a phrase is kept if its rarest word is used in this phrase at least this often
+const PHRASE_THRESHOLD: f32 = 0.5;
+const MIN_PHRASE_COUNT: f32 = 3.;
+// how many surface forms we remember for each stem
+const MAX_FORMS: usize = 8;
+// compaction only happens once this many tokens are interned, and twice as many as after the last one
+const MIN_COMPACT_LEN: usize = 10_000;
+// weights are rescaled when the newest message is this many half-lives past the landmark, to stay within f32 range
+const RESCALE_HALF_LIVES: f32 = 16.;
 
+#[derive(Serialize, Deserialize)]
 pub struct Idioms<P: Hash+Eq, U: Hash+Eq> {
     places: HashMap<P, TopFreqs<PLACE_VOC_LEN>>,
     people: HashMap<U, TopFreqs<PERSON_VOC_LEN>>,
     tokens: BiMap<String, usize>,
+    /// Ids of compacted tokens aren't reused, so traces of old messages can't retract the wrong token
+    next_token: usize,
+    /// Phrases are counted separately so they don't crowd out the words of the places
+    phrases: HashMap<P, TopFreqs<PLACE_PHRASES_LEN>>,
+    people_phrases: HashMap<U, TopFreqs<PERSON_PHRASES_LEN>>,
+    /// How often each surface form of a stemmed token was used, to display the most common one
+    forms: HashMap<usize, HashMap<String, u32>>,
+    /// In seconds, no decay if None
+    half_life: Option<f32>,
+    /// Unix timestamp at which a message weighs 1
+    landmark: i64,
+    /// Same as places and people but over recent time windows, without decay
+    place_buckets: HashMap<P, Buckets<PLACE_VOC_LEN>>,
+    people_buckets: HashMap<U, Buckets<PERSON_VOC_LEN>>,
+    place_phrase_buckets: HashMap<P, Buckets<PLACE_PHRASES_LEN>>,
+    people_phrase_buckets: HashMap<U, Buckets<PERSON_PHRASES_LEN>>,
+    /// How many tokens were left after the last compaction
+    #[serde(skip)]
+    compacted_len: usize,
 }
 
+fn intern(tokens: &mut BiMap<String, usize>, next_token: &mut usize, token: &str) -> usize {
+    match tokens.get_by_left(token) {
+        Some(v) => *v,
+        None => {
+            let v = *next_token;
+            *next_token = v + 1;
+            tokens.insert(token.to_string(), v);
+            v
+        }
+    }
+}
 
-impl<P: Hash+Eq, U: Hash+Eq> Idioms<P, U> {
-    pub fn new() -> Self {
+/// Phrases are stored as their words joined by spaces, since tokens never contain whitespace
+fn is_phrase(token: &str) -> bool {
+    token.contains(' ')
+}
+
+fn now() -> i64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs() as i64)
+}
+
+impl<P: Hash+Eq+Clone, U: Hash+Eq+Clone> Idioms<P, U> {
+    /// Messages lose half their weight every half_life days, if there's one
+    pub fn new(half_life: Option<f32>) -> Self {
         let mut tokens = BiMap::new();
         // reserve slot 0 for empty string
         tokens.insert(String::new(), 0);
         Self {
-            places: HashMap::new(), people: HashMap::new(), tokens
+            places: HashMap::new(), people: HashMap::new(), tokens, next_token: 1, 
+            phrases: HashMap::new(), people_phrases: HashMap::new(), forms: HashMap::new(),
+            half_life: half_life.map(|days| days * 3600. * 24.), landmark: 0,
+            place_buckets: HashMap::new(), people_buckets: HashMap::new(), 
+            place_phrase_buckets: HashMap::new(), people_phrase_buckets: HashMap::new(), compacted_len: 0
+        }
+    }
+
+    /// Rather than decaying every count as time passes, newer messages weigh exponentially more,
+    /// which gives the same ranking whatever order messages are read in
+    fn weight(&self, time: i64) -> f32 {
+        match self.half_life {
+            Some(half_life) => ((time - self.landmark) as f32 / half_life).exp2(),
+            None => 1.
+        }
+    }
+
+    /// Moves the landmark to this time if weights are getting too big
+    fn advance_landmark(&mut self, time: i64) {
+        let Some(half_life) = self.half_life else {
+            return;
+        };
+        if (time - self.landmark) as f32 / half_life > RESCALE_HALF_LIVES {
+            let factor = self.weight(time).recip();
+            self.places.values_mut().for_each(|voc| voc.scale(factor));
+            self.people.values_mut().for_each(|voc| voc.scale(factor));
+            self.phrases.values_mut().for_each(|voc| voc.scale(factor));
+            self.people_phrases.values_mut().for_each(|voc| voc.scale(factor));
+            self.landmark = time;
+        }
+    }
+
+    /// Counts a use of the surface form of a token.  
+    /// Forms are only tracked once a token has been seen in a form that differs from it.
+    fn add_form(&mut self, idx: usize, token: &str, surface: &str) {
+        if token == surface && !self.forms.contains_key(&idx) {
+            return;
+        }
+        if !self.forms.contains_key(&idx) {
+            // until now the token was only ever used as is
+            let count = (self.count(idx, &mut HashMap::new()) / self.weight(now())) as u32;
+            let forms = if count > 0 { HashMap::from([(token.to_string(), count)]) } else { HashMap::new() };
+            self.forms.insert(idx, forms);
+        }
+        let forms = self.forms.get_mut(&idx).unwrap();
+        if let Some(count) = forms.get_mut(surface) {
+            *count += 1;
+        } else if forms.len() < MAX_FORMS {
+            forms.insert(surface.to_string(), 1);
+        }
+    }
+
+    /// Takes (key, surface form) pairs, where the key is the stem of the token or the token itself, 
+    /// and the unix timestamp of the message. Phrases don't span the GAPs, which aren't counted.  
+    /// Returns the tokens (and phrases) that were counted, so they can be retracted later
+    pub fn update(&mut self, place: P, person: U, tokens: Vec<(String, String)>, time: i64) -> Vec<usize> {
+        self.advance_landmark(time);
+        let weight = self.weight(time);
+        let mut indices = Vec::new();
+        for (token, surface) in tokens.iter().filter(|(token, _)| token != GAP) {
+            let idx = intern(&mut self.tokens, &mut self.next_token, token);
+            self.add_form(idx, token, surface);
+            indices.push(idx);
+        }
+        let mut phrase_indices = Vec::new();
+        for run in tokens.split(|(token, _)| token == GAP) {
+            for len in 2..=MAX_PHRASE_LEN {
+                // emojis and mentions are displayed as images or names, they can't be part of a phrase
+                for words in run.windows(len).filter(|words| words.iter().all(|(word, _)| !word.starts_with('<'))) {
+                    let phrase = words.iter().map(|(word, _)| word).join(" ");
+                    let idx = intern(&mut self.tokens, &mut self.next_token, &phrase);
+                    self.add_form(idx, &phrase, &words.iter().map(|(_, surface)| surface).join(" "));
+                    phrase_indices.push(idx);
+                }
+            }
+        }
+        let now = now();
+        let place_voc = self.places.entry(place.clone()).or_insert(TopFreqs::new());
+        let user_voc = self.people.entry(person.clone()).or_insert(TopFreqs::new());
+        let place_buckets = self.place_buckets.entry(place.clone()).or_insert(Buckets::new());
+        let user_buckets = self.people_buckets.entry(person.clone()).or_insert(Buckets::new());
+        for idx in &indices {
+            place_voc.add(*idx, weight);
+            user_voc.add(*idx, weight);
+            place_buckets.add(*idx, 1., time, now);
+            user_buckets.add(*idx, 1., time, now);
+        }
+        let place_phrases = self.phrases.entry(place.clone()).or_insert(TopFreqs::new());
+        let user_phrases = self.people_phrases.entry(person.clone()).or_insert(TopFreqs::new());
+        let place_phrase_buckets = self.place_phrase_buckets.entry(place).or_insert(Buckets::new());
+        let user_phrase_buckets = self.people_phrase_buckets.entry(person).or_insert(Buckets::new());
+        for idx in &phrase_indices {
+            place_phrases.add(*idx, weight);
+            user_phrases.add(*idx, weight);
+            place_phrase_buckets.add(*idx, 1., time, now);
+            user_phrase_buckets.add(*idx, 1., time, now);
+        }
+        indices.extend(phrase_indices);
+        indices
+    }
+
+    /// Undoes an update, given what it returned and the timestamp of the message
+    pub fn retract(&mut self, place: &P, person: &U, indices: &[usize], time: i64) {
+        let weight = self.weight(time);
+        // words are only in the vocabularies and phrases only in the phrases, 
+        // so we can try to remove each from both
+        if let Some(place_voc) = self.places.get_mut(place) {
+            indices.iter().for_each(|idx| place_voc.remove(idx, weight));
+        }
+        if let Some(place_phrases) = self.phrases.get_mut(place) {
+            indices.iter().for_each(|idx| place_phrases.remove(idx, weight));
+        }
+        if let Some(user_voc) = self.people.get_mut(person) {
+            indices.iter().for_each(|idx| user_voc.remove(idx, weight));
+        }
+        if let Some(user_phrases) = self.people_phrases.get_mut(person) {
+            indices.iter().for_each(|idx| user_phrases.remove(idx, weight));
+        }
+        if let Some(place_buckets) = self.place_buckets.get_mut(place) {
+            indices.iter().for_each(|idx| place_buckets.remove(*idx, 1., time));
+        }
+        if let Some(place_phrase_buckets) = self.place_phrase_buckets.get_mut(place) {
+            indices.iter().for_each(|idx| place_phrase_buckets.remove(*idx, 1., time));
+        }
+        if let Some(user_buckets) = self.people_buckets.get_mut(person) {
+            indices.iter().for_each(|idx| user_buckets.remove(*idx, 1., time));
+        }
+        if let Some(user_phrase_buckets) = self.people_phrase_buckets.get_mut(person) {
+            indices.iter().for_each(|idx| user_phrase_buckets.remove(*idx, 1., time));
+        }
+    }
+
+    /// How often a token was used across all places, in landmark units
+    fn count(&self, idx: usize, counts: &mut HashMap<usize, f32>) -> f32 {
+        *counts.entry(idx).or_insert_with(|| 
+            self.places.values().map(|voc| voc.get(&idx)).sum::<f32>()
+            + self.phrases.values().map(|voc| voc.get(&idx)).sum::<f32>()
+        )
+    }
+
+    /// The most common surface form of a token
+    fn display(&self, idx: usize, token: &str) -> String {
+        self.forms.get(&idx)
+            .and_then(|forms| forms.iter().max_by(|(form1, count1), (form2, count2)| count1.cmp(count2).then(form2.cmp(form1))))
+            .map_or(token, |(form, _)| form)
+            .to_string()
+    }
+
+    /// Keeps the phrases whose words are used together more often than apart, 
+    /// and removes the words (and shorter phrases) they're made of
+    fn to_strings(&self, voc: impl IntoIterator<Item = (usize, f32)>) -> Vec<(String, f32)> {
+        let current = self.weight(now());
+        let mut counts = HashMap::new();
+        let voc = voc.into_iter()
+            .filter(|(idx, _)| *idx != 0)
+            .map(|(idx, v)| (idx, self.tokens.get_by_right(&idx).unwrap(), v))
+            .collect_vec();
+        let mut suppressed = HashSet::new();
+        let mut phrases = HashSet::new();
+        for (idx, token, _) in voc.iter().filter(|(_, token, _)| is_phrase(token)) {
+            let together = self.count(*idx, &mut counts);
+            let words = token.split(' ').collect_vec();
+            let apart = words.iter()
+                .map(|word| self.tokens.get_by_left(*word).map_or(0., |word_idx| self.count(*word_idx, &mut counts)))
+                .fold(f32::INFINITY, f32::min);
+            if together >= MIN_PHRASE_COUNT*current && together >= apart*PHRASE_THRESHOLD {
+                phrases.insert(*idx);
+                for len in 1..words.len() {
+                    suppressed.extend(words.windows(len).map(|sub| sub.join(" ")));
+                }
+            }
         }
+        voc.into_iter()
+            .filter(|(idx, token, _)| 
+                (!is_phrase(token) || phrases.contains(idx)) && !suppressed.contains(*token)
+            )
+            .map(|(idx, token, v)| (self.display(idx, token), v))
+            .collect_vec()
     }
 
-    pub fn update(&mut self, place: P, person: U, tokens: Vec<String>) {
-        let place_voc = self.places.entry(place).or_insert(TopFreqs::new());
-        let user_voc = self.people.entry(person).or_insert(TopFreqs::new());
-        for token in tokens {
-            let idx = match self.tokens.get_by_left(&token) {
-                Some(v) => *v,
-                None => {
-                    let v = self.tokens.len();
-                    self.tokens.insert(token, v);
-                    v
+    /// How many tokens (and phrases) are interned
+    pub fn interned(&self) -> usize {
+        self.tokens.len()
+    }
+
+    /// Whether enough tokens were interned since the last compaction for it to be worth doing again
+    pub fn should_compact(&self) -> bool {
+        self.tokens.len() >= MIN_COMPACT_LEN.max(2*self.compacted_len)
+    }
+
+    /// Forgets the tokens that are no longer in any vocabulary, returns how many were freed
+    pub fn compact(&mut self) -> usize {
+        let now = now();
+        self.place_buckets.values_mut().for_each(|buckets| buckets.prune(now));
+        self.people_buckets.values_mut().for_each(|buckets| buckets.prune(now));
+        self.place_phrase_buckets.values_mut().for_each(|buckets| buckets.prune(now));
+        self.people_phrase_buckets.values_mut().for_each(|buckets| buckets.prune(now));
+        // slot 0 is reserved for the empty string
+        let mut used = HashSet::from([0]);
+        self.places.values().flat_map(|voc| voc.iter())
+            .chain(self.phrases.values().flat_map(|voc| voc.iter()))
+            .chain(self.people.values().flat_map(|voc| voc.iter()))
+            .chain(self.people_phrases.values().flat_map(|voc| voc.iter()))
+            .for_each(|(idx, _)| { used.insert(idx); });
+        self.place_buckets.values().flat_map(|buckets| buckets.indices())
+            .chain(self.people_buckets.values().flat_map(|buckets| buckets.indices()))
+            .chain(self.place_phrase_buckets.values().flat_map(|buckets| buckets.indices()))
+            .chain(self.people_phrase_buckets.values().flat_map(|buckets| buckets.indices()))
+            .for_each(|idx| { used.insert(idx); });
+        let before = self.tokens.len();
+        self.tokens.retain(|_, idx| used.contains(idx));
+        self.forms.retain(|idx, _| used.contains(idx));
+        self.compacted_len = self.tokens.len();
+        before - self.tokens.len()
+    }
+
+    /// Erases everything we know about this person
+    pub fn forget(&mut self, person: &U) {
+        self.people.remove(person);
+        self.people_phrases.remove(person);
+        self.people_buckets.remove(person);
+        self.people_phrase_buckets.remove(person);
+    }
+
+    /// Counts of the period, decayed counts are brought back to the scale of a message sent now
+    fn as_of_now(&self, voc: impl IntoIterator<Item = (usize, f32)>, period: Period) -> HashMap<usize, f32> {
+        let current = if period == Period::All { self.weight(now()) } else { 1. };
+        let mut merged = HashMap::new();
+        voc.into_iter().for_each(|(idx, v)| *merged.entry(idx).or_default() += v / current);
+        merged
+    }
+
+    fn person_voc(&self, person: &U, period: Period) -> HashMap<usize, f32> {
+        match period {
+            Period::All => {
+                let words = self.people.get(person).into_iter().flat_map(|voc| voc.iter());
+                let phrases = self.people_phrases.get(person).into_iter().flat_map(|voc| voc.iter());
+                self.as_of_now(words.chain(phrases), period)
+            },
+            _ => {
+                let mut voc = self.people_buckets.get(person).map(|buckets| buckets.window(period, now())).unwrap_or_default();
+                if let Some(buckets) = self.people_phrase_buckets.get(person) {
+                    buckets.window(period, now()).into_iter().for_each(|(idx, v)| *voc.entry(idx).or_default() += v);
+                }
+                voc
+            }
+        }
+    }
+
+    fn place_voc(&self, place: &P, period: Period) -> HashMap<usize, f32> {
+        match period {
+            Period::All => {
+                let words = self.places.get(place).into_iter().flat_map(|voc| voc.iter());
+                let phrases = self.phrases.get(place).into_iter().flat_map(|voc| voc.iter());
+                self.as_of_now(words.chain(phrases), period)
+            },
+            _ => {
+                let mut voc = self.place_buckets.get(place).map(|buckets| buckets.window(period, now())).unwrap_or_default();
+                if let Some(buckets) = self.place_phrase_buckets.get(place) {
+                    buckets.window(period, now()).into_iter().for_each(|(idx, v)| *voc.entry(idx).or_default() += v);
                 }
-            };
-            place_voc.add(idx, 1.);
-            let inctx_value = (-place_voc.get(&idx)).exp()*10.;
-            user_voc.add(idx, inctx_value);
+                voc
+            }
         }
     }
 
-    pub fn idiom(&self, person: U) -> Vec<(String, f32)> {
-        let res = match self.people.get(&person) {
-            Some(voc) => voc.data.clone().into_iter()
-                .filter(|(idx, _)| *idx != 0).collect_vec(),
-            None => Vec::new()
+    /// Weights the tokens of the foreground against the whole guild
+    fn score(&self, foreground: HashMap<usize, f32>, period: Period, scoring: Scoring) -> Vec<(String, f32)> {
+        let scores = match scoring {
+            Scoring::Frequency => foreground.into_iter().collect(),
+            Scoring::TfIdf => {
+                let channels = self.places.keys().map(|place| self.place_voc(place, period)).collect_vec();
+                tf_idf(&foreground, &channels)
+            },
+            Scoring::LogOdds => {
+                let mut background = HashMap::new();
+                self.places.keys()
+                    .flat_map(|place| self.place_voc(place, period))
+                    .for_each(|(idx, v)| *background.entry(idx).or_default() += v);
+                log_odds(&foreground, &background)
+            }
         };
-        res.into_iter()
-        .map(|(idx, v)| (self.tokens.get_by_right(&idx).unwrap().clone(), v))
-        .collect_vec()
+        self.to_strings(scores)
+    }
+
+    pub fn idiom(&self, person: U, period: Period, scoring: Scoring) -> Vec<(String, f32)> {
+        self.score(self.person_voc(&person, period), period, scoring)
+    }
+
+    pub fn place_idiom(&self, place: P, period: Period, scoring: Scoring) -> Vec<(String, f32)> {
+        self.score(self.place_voc(&place, period), period, scoring)
+    }
+
+    /// The merged idioms of a group of people
+    pub fn group_idiom<'a>(&self, people: impl IntoIterator<Item = &'a U>, period: Period, scoring: Scoring) -> Vec<(String, f32)> where U: 'a {
+        let mut merged: HashMap<usize, f32> = HashMap::new();
+        people.into_iter()
+            .flat_map(|person| self.person_voc(person, period))
+            .for_each(|(idx, v)| *merged.entry(idx).or_default() += v);
+        self.score(merged, period, scoring)
+    }
+
+    /// The vocabulary of everyone, by raw frequency since there's nothing to compare it to
+    pub fn server_idiom(&self, period: Period) -> Vec<(String, f32)> {
+        self.group_idiom(self.people.keys(), period, Scoring::Frequency)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use super::{Idioms, now, GAP};
+    use crate::idiom::{Period, Scoring};
+
+    const PLACE: u64 = 1;
+    const PERSON: u64 = 2;
+
+    fn message(words: &[&str]) -> Vec<(String, String)> {
+        words.iter().map(|word| (word.to_string(), word.to_string())).collect()
+    }
+
+    fn send(idioms: &mut Idioms<u64, u64>, words: &[&str], times: usize) {
+        (0..times).for_each(|_| { idioms.update(PLACE, PERSON, message(words), now()); });
+    }
+
+    fn idiom(idioms: &Idioms<u64, u64>) -> Vec<String> {
+        idioms.idiom(PERSON, Period::All, Scoring::Frequency).into_iter().map(|(token, _)| token).collect()
+    }
+
+    #[test]
+    fn phrases_dont_span_gaps() {
+        let mut idioms = Idioms::new(None);
+        send(&mut idioms, &["ice", "cream", GAP, "truck"], 1);
+        assert!(idioms.tokens.contains_left("ice cream"));
+        assert!(!idioms.tokens.contains_left("cream truck"));
+        assert!(!idioms.tokens.contains_left("ice cream truck"));
+        // gaps aren't counted as words either
+        assert!(idioms.places[&PLACE].iter().all(|(idx, _)| idx != 0));
+    }
+
+    #[test]
+    fn person_phrases_are_counted_apart_from_words() {
+        let mut idioms = Idioms::new(None);
+        send(&mut idioms, &["ice", "cream", "truck"], 1);
+        let phrase = |idx: usize| idioms.tokens.get_by_right(&idx).unwrap().contains(' ');
+        assert_eq!(idioms.people[&PERSON].iter().filter(|(idx, _)| phrase(*idx)).count(), 0);
+        assert_eq!(idioms.people_phrases[&PERSON].iter().filter(|(idx, _)| phrase(*idx)).count(), 3);
+        assert_eq!(idioms.people_buckets[&PERSON].indices().filter(|idx| phrase(*idx)).count(), 0);
+    }
+
+    #[test]
+    fn frequent_phrases_suppress_their_words() {
+        let mut idioms = Idioms::new(None);
+        send(&mut idioms, &["ice", "cream"], 5);
+        send(&mut idioms, &["pizza"], 1);
+        assert_eq!(idiom(&idioms).into_iter().sorted().collect_vec(), vec!["ice cream", "pizza"]);
+    }
+
+    #[test]
+    fn phrases_of_words_mostly_used_apart_are_dropped() {
+        let mut idioms = Idioms::new(None);
+        send(&mut idioms, &["ice", "cream"], 3);
+        send(&mut idioms, &["ice"], 10);
+        send(&mut idioms, &["cream"], 10);
+        assert_eq!(idiom(&idioms).into_iter().sorted().collect_vec(), vec!["cream", "ice"]);
+    }
+
+    #[test]
+    fn rare_phrases_are_dropped() {
+        let mut idioms = Idioms::new(None);
+        send(&mut idioms, &["ice", "cream"], 1);
+        assert_eq!(idiom(&idioms).into_iter().sorted().collect_vec(), vec!["cream", "ice"]);
+    }
+
+    #[test]
+    fn retract_takes_back_phrases() {
+        let mut idioms = Idioms::new(None);
+        let time = now();
+        let indices = idioms.update(PLACE, PERSON, message(&["ice", "cream"]), time);
+        idioms.retract(&PLACE, &PERSON, &indices, time);
+        assert!(idioms.people_phrases[&PERSON].iter().all(|(_, count)| count == 0.));
+        assert!(idioms.phrases[&PLACE].iter().all(|(_, count)| count == 0.));
+    }
+}
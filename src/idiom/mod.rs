@@ -1,5 +1,16 @@
+#[allow(clippy::module_inception)]
 mod idiom;
 mod top_freqs;
 mod text_utils;
+mod stopwords;
+mod segmentation;
+mod stemming;
+mod buckets;
+mod scoring;
 pub use idiom::Idioms;
-pub use text_utils::tokenize;
\ No newline at end of file
+pub use text_utils::{tokenize, stem_tokens, MarkdownRules, UrlRule};
+pub use stopwords::{detect_lang, remove_stopwords};
+pub use segmentation::{is_emoji, load_japanese_dictionary};
+pub use stemming::Stemmer;
+pub use buckets::Period;
+pub use scoring::Scoring;
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, HashSet};
+use serde::{Deserialize, Serialize};
+// how much the guild's usage weighs as a prior in log-odds, in number of tokens
+const PRIOR_STRENGTH: f32 = 1000.;
+// so that tokens missing from the background don't get an infinite score
+const MIN_PRIOR: f32 = 0.01;
+
+/// How the tokens of a cloud are weighted
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scoring {
+    /// How often the tokens are used
+    Frequency,
+    /// Term frequency times the inverse of the number of channels the token is used in
+    TfIdf,
+    /// Weighted log-odds against the rest of the guild, with the guild's usage as prior
+    LogOdds,
+}
+
+impl Scoring {
+    pub const ALL: [Scoring; 3] = [Scoring::Frequency, Scoring::TfIdf, Scoring::LogOdds];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scoring::Frequency => "frequency",
+            Scoring::TfIdf => "tf-idf",
+            Scoring::LogOdds => "log-odds",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|scoring| scoring.name() == name)
+    }
+}
+
+/// The foreground's term frequencies, weighted by the smoothed inverse document frequency of the tokens
+pub fn tf_idf(foreground: &HashMap<usize, f32>, documents: &[HashMap<usize, f32>]) -> Vec<(usize, f32)> {
+    let total: f32 = foreground.values().sum();
+    if total <= 0. {
+        return Vec::new();
+    }
+    let n = documents.len() as f32;
+    foreground.iter().map(|(idx, count)| {
+        let df = documents.iter().filter(|doc| doc.get(idx).is_some_and(|v| *v > 0.)).count() as f32;
+        let idf = ((1. + n) / (1. + df)).ln() + 1.;
+        (*idx, count / total * idf)
+    }).collect()
+}
+
+/// Z-scores of the log-odds ratio between the foreground and the rest of the background
+/// (which includes the foreground), using the background as an informative Dirichlet prior.
+/// Only the tokens that are over-represented in the foreground are kept.
+pub fn log_odds(foreground: &HashMap<usize, f32>, background: &HashMap<usize, f32>) -> Vec<(usize, f32)> {
+    let background_total: f32 = background.values().sum();
+    let n_fg: f32 = foreground.values().sum();
+    let tokens: HashSet<usize> = foreground.keys().chain(background.keys()).copied().collect();
+    let rest = |idx: &usize| (background.get(idx).unwrap_or(&0.) - foreground.get(idx).unwrap_or(&0.)).max(0.);
+    let n_rest: f32 = tokens.iter().map(rest).sum();
+    if n_fg <= 0. || background_total <= 0. {
+        return Vec::new();
+    }
+    foreground.iter().filter_map(|(idx, y_fg)| {
+        let prior = (PRIOR_STRENGTH * background.get(idx).unwrap_or(&0.) / background_total).max(MIN_PRIOR);
+        let y_rest = rest(idx);
+        let delta = ((y_fg + prior) / (n_fg + PRIOR_STRENGTH - y_fg - prior)).ln()
+            - ((y_rest + prior) / (n_rest + PRIOR_STRENGTH - y_rest - prior)).ln();
+        let variance = 1. / (y_fg + prior) + 1. / (y_rest + prior);
+        let z = delta / variance.sqrt();
+        (z > 0.).then_some((*idx, z))
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use itertools::Itertools;
+    use super::{log_odds, tf_idf};
+
+    const THE: usize = 1;
+    const GAME: usize = 2;
+    const PINEAPPLE: usize = 3;
+    const RARE: usize = 4;
+
+    fn voc(counts: &[(usize, f32)]) -> HashMap<usize, f32> {
+        counts.iter().copied().collect()
+    }
+
+    fn ranking(scores: Vec<(usize, f32)>) -> Vec<usize> {
+        scores.into_iter()
+            .sorted_by(|(_, a), (_, b)| b.total_cmp(a))
+            .map(|(idx, _)| idx)
+            .collect()
+    }
+
+    /// Everyone says "the" a lot and talks about games in every channel, 
+    /// but only this user talks about pineapples, in a single channel
+    fn corpus() -> (HashMap<usize, f32>, Vec<HashMap<usize, f32>>) {
+        let user = voc(&[(THE, 20.), (GAME, 10.), (PINEAPPLE, 12.)]);
+        let mut channels = vec![voc(&[(THE, 500.), (GAME, 100.), (PINEAPPLE, 12.)])];
+        channels.extend((0..9).map(|i| voc(&[(THE, 300. + i as f32), (GAME, 60.), (RARE, 1.)])));
+        (user, channels)
+    }
+
+    fn merge(vocs: &[HashMap<usize, f32>]) -> HashMap<usize, f32> {
+        let mut merged = HashMap::new();
+        vocs.iter().flatten().for_each(|(idx, v)| *merged.entry(*idx).or_insert(0.) += v);
+        merged
+    }
+
+    #[test]
+    fn tf_idf_favors_tokens_used_in_few_channels() {
+        let (user, channels) = corpus();
+        assert_eq!(ranking(tf_idf(&user, &channels))[0], PINEAPPLE);
+    }
+
+    #[test]
+    fn tf_idf_is_frequency_when_tokens_are_everywhere() {
+        let user = voc(&[(THE, 30.), (GAME, 10.)]);
+        let channels = vec![voc(&[(THE, 1.), (GAME, 1.)]), voc(&[(THE, 1.), (GAME, 1.)])];
+        let scores: HashMap<usize, f32> = tf_idf(&user, &channels).into_iter().collect();
+        assert!((scores[&THE] / scores[&GAME] - 3.).abs() < 1e-5);
+    }
+
+    #[test]
+    fn log_odds_favors_over_represented_tokens() {
+        let (user, channels) = corpus();
+        assert_eq!(ranking(log_odds(&user, &merge(&channels)))[0], PINEAPPLE);
+    }
+
+    #[test]
+    fn log_odds_drops_tokens_used_like_everyone_else() {
+        // the user says "the" and "game" exactly as often as the rest of the guild
+        let user = voc(&[(THE, 50.), (GAME, 10.)]);
+        let rest = voc(&[(THE, 5000.), (GAME, 1000.)]);
+        let background = merge(&[user.clone(), rest]);
+        assert!(log_odds(&user, &background).iter().all(|(_, z)| *z < 1e-3));
+    }
+
+    #[test]
+    fn log_odds_grows_with_evidence() {
+        let background = voc(&[(THE, 1000.), (PINEAPPLE, 10.)]);
+        let few = voc(&[(THE, 10.), (PINEAPPLE, 2.)]);
+        let many = voc(&[(THE, 50.), (PINEAPPLE, 10.)]);
+        let score = |fg: &HashMap<usize, f32>| log_odds(fg, &background).into_iter()
+            .find(|(idx, _)| *idx == PINEAPPLE).unwrap().1;
+        assert!(score(&many) > score(&few));
+    }
+
+    #[test]
+    fn empty_inputs_give_empty_scores() {
+        assert!(tf_idf(&HashMap::new(), &[]).is_empty());
+        assert!(log_odds(&HashMap::new(), &HashMap::new()).is_empty());
+    }
+}
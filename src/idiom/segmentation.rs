@@ -0,0 +1,174 @@
+use std::{borrow::Cow, sync::OnceLock};
+use anyhow::{Result, anyhow};
+use itertools::Itertools;
+use jieba_rs::Jieba;
+use lazy_static::lazy_static;
+use lindera::{dictionary::load_dictionary, mode::Mode, segmenter::Segmenter};
+use unic_emoji_char::is_emoji_presentation;
+use unicode_normalization::UnicodeNormalization;
+use unicode_segmentation::UnicodeSegmentation;
+const VARIATION_SELECTOR_16: char = '\u{FE0F}';
+
+lazy_static! {
+    // chinese doesn't use spaces, jieba ships with a dictionary to split it into words
+    static ref JIEBA: Jieba = Jieba::new();
+}
+
+// japanese dictionaries are too big to ship, so there's only one if the bot was given one
+static JAPANESE: OnceLock<Segmenter> = OnceLock::new();
+
+/// Splits japanese with the Lindera dictionary (such as a build of IPADIC) at this path, 
+/// instead of splitting it by scripts
+pub fn load_japanese_dictionary(path: &str) -> Result<()> {
+    let dictionary = load_dictionary(path).map_err(|why| anyhow!("Couldn't load {}: {}", path, why))?;
+    JAPANESE.set(Segmenter::new(Mode::Normal, dictionary, None))
+        .map_err(|_| anyhow!("A japanese dictionary was already loaded"))
+}
+
+fn is_kana(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}')
+}
+
+fn is_katakana(c: char) -> bool {
+    // the prolonged sound mark is part of the block
+    matches!(c, '\u{30A0}'..='\u{30FF}')
+}
+
+fn is_cjk(c: char) -> bool {
+    matches!(c,
+        '\u{3040}'..='\u{30FF}' // Hiragana and Katakana
+        | '\u{3400}'..='\u{4DBF}' | '\u{4E00}'..='\u{9FFF}' | '\u{F900}'..='\u{FAFF}' // Han
+        | '\u{20000}'..='\u{2A6DF}'
+    )
+}
+
+/// Whether the token is a standard emoji (possibly made of several code points)
+pub fn is_emoji(token: &str) -> bool {
+    token.chars().any(|c| is_emoji_presentation(c) || c == VARIATION_SELECTOR_16)
+        && !token.chars().any(char::is_alphanumeric)
+}
+
+/// NFKC normalization turns full-width and other compatibility characters into their usual form
+pub fn normalize(text: &str) -> String {
+    text.nfkc()
+        .map(|c| if c == '\u{2019}' { '\'' } else { c })
+        .collect()
+}
+
+/// Splits a run of CJK characters into words.  
+/// Japanese is split with its dictionary if there is one, otherwise kana are kept together by script 
+/// and kanji are split like chinese, with jieba.
+fn segment_cjk(run: &str) -> Vec<String> {
+    if run.chars().any(is_kana) {
+        if let Some(Ok(tokens)) = JAPANESE.get().map(|segmenter| segmenter.segment(Cow::Borrowed(run))) {
+            return tokens.into_iter().map(|token| token.surface.into_owned()).collect();
+        }
+    }
+    let mut res = Vec::new();
+    for (_, script) in &run.chars().chunk_by(|c| if is_katakana(*c) { 1 } else if is_kana(*c) { 2 } else { 0 }) {
+        let part: String = script.collect();
+        if part.chars().next().is_some_and(is_kana) {
+            res.push(part);
+        } else {
+            res.extend(JIEBA.cut(&part, true).into_iter().map(str::to_string));
+        }
+    }
+    res
+}
+
+/// Splits text into words and emojis following the Unicode rules, dropping spaces and punctuation
+pub fn segment(text: &str) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut cjk_run = String::new();
+    for segment in text.split_word_bounds() {
+        if segment.chars().all(is_cjk) {
+            cjk_run.push_str(segment);
+            continue;
+        }
+        if !cjk_run.is_empty() {
+            res.extend(segment_cjk(&cjk_run));
+            cjk_run.clear();
+        }
+        if segment.chars().any(char::is_alphanumeric) || is_emoji(segment) {
+            res.push(segment.to_string());
+        }
+    }
+    if !cjk_run.is_empty() {
+        res.extend(segment_cjk(&cjk_run));
+    }
+    res
+}
+
+/// Shortens letters repeated 3 times or more to 2, so that "nooooo" and "nooo" are the same word
+pub fn collapse_repeats(token: &str) -> String {
+    let mut res = String::with_capacity(token.len());
+    let mut last = None;
+    let mut count = 0;
+    for c in token.chars() {
+        if Some(c) == last {
+            count += 1;
+        } else {
+            last = Some(c);
+            count = 1;
+        }
+        if count <= 2 || !c.is_alphabetic() {
+            res.push(c);
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{collapse_repeats, is_emoji, normalize, segment};
+
+    #[test]
+    fn normalize_folds_compatibility_characters() {
+        assert_eq!(normalize("ｈｅｌｌｏ"), "hello");
+        assert_eq!(normalize("ﬁne"), "fine");
+        assert_eq!(normalize("don’t"), "don't");
+    }
+
+    #[test]
+    fn collapse_repeats_keeps_two_letters() {
+        assert_eq!(collapse_repeats("noooooo"), "noo");
+        assert_eq!(collapse_repeats("nooo"), collapse_repeats("nooooo"));
+        assert_eq!(collapse_repeats("book"), "book");
+        // only letters are collapsed, numbers keep their meaning
+        assert_eq!(collapse_repeats("1000"), "1000");
+    }
+
+    #[test]
+    fn emojis_are_recognized() {
+        assert!(is_emoji("😀"));
+        assert!(is_emoji("❤️"));
+        assert!(is_emoji("👍🏽"));
+        assert!(!is_emoji("a"));
+        assert!(!is_emoji("42"));
+    }
+
+    #[test]
+    fn emojis_are_split_from_words() {
+        assert_eq!(segment("nice😀😀"), vec!["nice", "😀", "😀"]);
+        assert_eq!(segment("love ❤️!"), vec!["love", "❤️"]);
+    }
+
+    #[test]
+    fn punctuation_and_spaces_are_dropped() {
+        assert_eq!(segment("hello, world!"), vec!["hello", "world"]);
+        assert_eq!(segment("don't stop"), vec!["don't", "stop"]);
+    }
+
+    #[test]
+    fn chinese_is_split_into_words() {
+        assert_eq!(segment("我们喜欢音乐"), vec!["我们", "喜欢", "音乐"]);
+        assert_eq!(segment("hello世界"), vec!["hello", "世界"]);
+        assert_eq!(segment("我来到北京清华大学"), vec!["我", "来到", "北京", "清华大学"]);
+    }
+
+    #[test]
+    fn kana_stay_together_without_a_japanese_dictionary() {
+        assert_eq!(segment("ポケモンが大好き"), vec!["ポケモン", "が", "大好", "き"]);
+        assert_eq!(segment("ありがとう"), vec!["ありがとう"]);
+    }
+}
@@ -0,0 +1,111 @@
+use rust_stemmers::Algorithm;
+use serde::{Deserialize, Serialize};
+use whatlang::Lang;
+
+/// Reduces the inflections of a word to a common stem, which is only used as a key and never displayed
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Stemmer {
+    English,
+    French,
+    Spanish,
+    German,
+    Italian,
+    Portuguese,
+}
+
+impl Stemmer {
+    pub const ALL: [Stemmer; 6] = [
+        Stemmer::English, Stemmer::French, Stemmer::Spanish, Stemmer::German, Stemmer::Italian, Stemmer::Portuguese
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Stemmer::English => "english",
+            Stemmer::French => "french",
+            Stemmer::Spanish => "spanish",
+            Stemmer::German => "german",
+            Stemmer::Italian => "italian",
+            Stemmer::Portuguese => "portuguese",
+        }
+    }
+
+    fn algorithm(&self) -> Algorithm {
+        match self {
+            Stemmer::English => Algorithm::English,
+            Stemmer::French => Algorithm::French,
+            Stemmer::Spanish => Algorithm::Spanish,
+            Stemmer::German => Algorithm::German,
+            Stemmer::Italian => Algorithm::Italian,
+            Stemmer::Portuguese => Algorithm::Portuguese,
+        }
+    }
+
+    /// The language of the words this stemmer is made for
+    pub fn lang(&self) -> Lang {
+        match self {
+            Stemmer::English => Lang::Eng,
+            Stemmer::French => Lang::Fra,
+            Stemmer::Spanish => Lang::Spa,
+            Stemmer::German => Lang::Deu,
+            Stemmer::Italian => Lang::Ita,
+            Stemmer::Portuguese => Lang::Por,
+        }
+    }
+
+    /// Only words made of letters are stemmed, emojis, mentions, links and numbers are kept as is
+    pub fn stem(&self, word: &str) -> String {
+        if !word.chars().all(|c| c.is_alphabetic() || c == '\'') {
+            return word.to_string();
+        }
+        // the Snowball stemmers expect lowercase words
+        rust_stemmers::Stemmer::create(self.algorithm()).stem(&word.to_lowercase()).into_owned()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Stemmer;
+
+    fn same_stem(stemmer: Stemmer, words: &[&str]) -> bool {
+        words.iter().all(|word| stemmer.stem(word) == stemmer.stem(words[0]))
+    }
+
+    #[test]
+    fn english_inflections_share_a_stem() {
+        assert!(same_stem(Stemmer::English, &["cat", "cats", "cat's"]));
+        assert!(same_stem(Stemmer::English, &["run", "running", "runs"]));
+        assert!(same_stem(Stemmer::English, &["party", "parties"]));
+        assert!(same_stem(Stemmer::English, &["generous", "generously"]));
+    }
+
+    #[test]
+    fn english_words_keep_their_meaning() {
+        assert_eq!(Stemmer::English.stem("speed"), "speed");
+        assert_eq!(Stemmer::English.stem("news"), "news");
+        assert!(!same_stem(Stemmer::English, &["during", "dur"]));
+        assert!(!same_stem(Stemmer::English, &["sing", "s"]));
+    }
+
+    #[test]
+    fn other_languages_share_a_stem() {
+        assert!(same_stem(Stemmer::French, &["chanteur", "chanteurs"]));
+        assert!(same_stem(Stemmer::French, &["manger", "mange", "mangé"]));
+        assert!(same_stem(Stemmer::Spanish, &["hablando", "hablar", "hablado"]));
+        assert!(same_stem(Stemmer::German, &["zeitungen", "zeitung"]));
+        assert!(same_stem(Stemmer::Italian, &["parlando", "parlare", "parlato"]));
+        assert!(same_stem(Stemmer::Portuguese, &["falando", "falar", "falado"]));
+    }
+
+    #[test]
+    fn stems_are_lowercase() {
+        assert_eq!(Stemmer::English.stem("Cats"), Stemmer::English.stem("cats"));
+    }
+
+    #[test]
+    fn only_words_are_stemmed() {
+        assert_eq!(Stemmer::English.stem("<:cats:123>"), "<:cats:123>");
+        assert_eq!(Stemmer::English.stem("2000s"), "2000s");
+        assert_eq!(Stemmer::English.stem("😀"), "😀");
+    }
+}
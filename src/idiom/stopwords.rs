@@ -0,0 +1,119 @@
+use std::collections::{HashMap, HashSet};
+use lazy_static::lazy_static;
+use whatlang::{detect, Lang};
+use super::text_utils::GAP;
+
+const EN: &[&str] = &[
+    "a", "about", "after", "again", "all", "am", "an", "and", "any", "are", "as", "at", "be", "because",
+    "been", "before", "being", "but", "by", "can", "could", "did", "do", "does", "doing", "don't", "for",
+    "from", "had", "has", "have", "having", "he", "her", "here", "hers", "him", "his", "how", "i", "i'm",
+    "if", "in", "into", "is", "it", "it's", "its", "just", "me", "my", "no", "not", "now", "of", "off",
+    "on", "only", "or", "other", "our", "out", "over", "own", "same", "she", "so", "some", "such", "than",
+    "that", "that's", "the", "their", "them", "then", "there", "these", "they", "this", "those", "to",
+    "too", "under", "until", "up", "very", "was", "we", "were", "what", "when", "where", "which", "while",
+    "who", "whom", "why", "will", "with", "would", "you", "you're", "your", "yours"
+];
+
+const FR: &[&str] = &[
+    "à", "au", "aux", "avec", "ce", "ces", "c'est", "cette", "dans", "de", "des", "du", "elle", "en",
+    "est", "et", "eux", "il", "ils", "j'ai", "je", "la", "le", "les", "leur", "lui", "ma", "mais", "me",
+    "même", "mes", "moi", "mon", "ne", "nos", "notre", "nous", "on", "ou", "où", "par", "pas", "pour",
+    "qu'il", "que", "qui", "sa", "se", "ses", "si", "son", "sur", "ta", "te", "tes", "toi", "ton", "tu",
+    "un", "une", "vos", "votre", "vous", "y", "ça", "était", "être", "été"
+];
+
+const ES: &[&str] = &[
+    "a", "al", "algo", "como", "con", "de", "del", "el", "ella", "en", "es", "esta", "este", "esto", "fue",
+    "ha", "la", "las", "le", "les", "lo", "los", "me", "mi", "muy", "más", "no", "nos", "o", "para",
+    "pero", "por", "porque", "que", "se", "si", "sin", "su", "sus", "sí", "también", "te", "tu", "un",
+    "una", "uno", "y", "ya", "yo", "él"
+];
+
+const DE: &[&str] = &[
+    "aber", "als", "am", "an", "auch", "auf", "aus", "bei", "bin", "bis", "da", "das", "dass", "dein",
+    "dem", "den", "der", "des", "die", "dir", "du", "ein", "eine", "einen", "er", "es", "für", "hat",
+    "ich", "ihr", "im", "in", "ist", "ja", "mein", "mich", "mir", "mit", "nicht", "noch", "nur", "oder",
+    "sich", "sie", "sind", "so", "und", "uns", "von", "war", "was", "wie", "wir", "zu", "zum", "zur"
+];
+
+const IT: &[&str] = &[
+    "a", "al", "alla", "anche", "che", "ci", "come", "con", "da", "del", "della", "di", "e", "è", "gli",
+    "ha", "ho", "i", "il", "in", "io", "la", "le", "lo", "ma", "mi", "ne", "non", "per", "più", "quello",
+    "se", "si", "sono", "su", "ti", "tu", "un", "una", "uno"
+];
+
+const PT: &[&str] = &[
+    "a", "ao", "as", "com", "como", "da", "das", "de", "do", "dos", "e", "ela", "ele", "em", "eu", "foi",
+    "isso", "já", "mais", "mas", "me", "meu", "minha", "na", "não", "no", "nos", "o", "os", "ou", "para",
+    "pela", "pelo", "por", "que", "se", "sem", "seu", "sua", "também", "tem", "um", "uma", "você", "é"
+];
+
+const NL: &[&str] = &[
+    "aan", "al", "als", "bij", "dan", "dat", "de", "die", "dit", "een", "en", "er", "het", "hij", "ik",
+    "in", "is", "je", "kan", "maar", "me", "met", "mijn", "niet", "nog", "nu", "of", "om", "op", "te",
+    "van", "voor", "wat", "was", "we", "wel", "ze", "zijn"
+];
+
+// Chat filler that shows up regardless of the language
+const CHAT: &[&str] = &[
+    "lol", "lmao", "xd", "ok", "okay", "oh", "ah", "yeah", "yes", "mdr", "ptdr", "jaja", "haha", "hahaha",
+    "mhm", "hmm", "uh", "um"
+];
+
+lazy_static! {
+    static ref STOPWORDS: HashMap<Lang, HashSet<&'static str>> = [
+        (Lang::Eng, EN), (Lang::Fra, FR), (Lang::Spa, ES), (Lang::Deu, DE),
+        (Lang::Ita, IT), (Lang::Por, PT), (Lang::Nld, NL)
+    ].into_iter().map(|(lang, words)| (lang, words.iter().copied().collect())).collect();
+}
+
+/// The language of a text, if we can tell it reliably
+pub fn detect_lang(text: &str) -> Option<Lang> {
+    detect(text).filter(|info| info.is_reliable()).map(|info| info.lang())
+}
+
+fn is_stopword(token: &str, lang: Option<Lang>) -> bool {
+    if CHAT.contains(&token) {
+        return true;
+    }
+    match lang {
+        Some(lang) => STOPWORDS.get(&lang).is_some_and(|words| words.contains(token)),
+        // the lists of other languages would remove real words, like "die" or "fue"
+        None => false
+    }
+}
+
+/// Replaces the stopwords of the language, and the custom ones, which must be lowercase, with a GAP.  
+/// Without a language only the chat filler and the custom stopwords are removed.
+pub fn remove_stopwords(tokens: Vec<String>, lang: Option<Lang>, custom: &HashSet<String>) -> Vec<String> {
+    tokens.into_iter().map(|token| {
+        let lower = token.to_lowercase();
+        if is_stopword(&lower, lang) || custom.contains(&lower) { GAP.to_string() } else { token }
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use whatlang::Lang;
+    use super::{remove_stopwords, GAP};
+
+    #[test]
+    fn stopwords_leave_a_gap() {
+        let tokens = ["piece", "of", "cake"].map(String::from).to_vec();
+        assert_eq!(remove_stopwords(tokens, Some(Lang::Eng), &HashSet::new()), vec!["piece", GAP, "cake"]);
+    }
+
+    #[test]
+    fn custom_stopwords_ignore_case() {
+        let tokens = ["Pineapple", "pizza"].map(String::from).to_vec();
+        let custom = HashSet::from(["pineapple".to_string()]);
+        assert_eq!(remove_stopwords(tokens, Some(Lang::Eng), &custom), vec![GAP, "pizza"]);
+    }
+
+    #[test]
+    fn unknown_languages_only_lose_chat_filler() {
+        let tokens = ["lol", "die", "hard"].map(String::from).to_vec();
+        assert_eq!(remove_stopwords(tokens, None, &HashSet::new()), vec![GAP, "die", "hard"]);
+    }
+}
@@ -1,11 +1,51 @@
-use itertools::Itertools;
 use lazy_static::lazy_static;
-use regex::Regex;
-const OPENING_PUNCT: &[char] = &['(', '[', '{', '\'', '"', '*', '`'];
-const CLOSING_PUNCT: &[char] = &[':', '.', '?', '!', '`', ';', ',', ')', ']', '}', '\'', '"', '*', '`'];
+use regex::{Captures, Regex};
+use serde::{Deserialize, Serialize};
+use super::segmentation::{normalize, segment, collapse_repeats};
+use super::stemming::Stemmer;
+// markdown emphasis markers are included so they get stripped too
+const OPENING_PUNCT: &[char] = &['(', '[', '{', '\'', '"', '*', '`', '_', '~', '|'];
+const CLOSING_PUNCT: &[char] = &[':', '.', '?', '!', '`', ';', ',', ')', ']', '}', '\'', '"', '*', '`', '_', '~', '|'];
+// the markdown we skip is replaced by this control character, so we know where the text was cut
+const GAP_MARK: &str = "\u{1F}";
+/// Stands in for the text that was removed, so that phrases aren't made of words that weren't next to each other
+pub const GAP: &str = "";
 
 lazy_static! {
-    static ref RE_TOKEN: Regex = Regex::new(r"\S+").unwrap();
+    // discord emojis, mentions and URLs mustn't be split into words
+    static ref RE_ATOMIC: Regex = Regex::new(r"<(?:a?:\w+:|@!?|@&|#)\d+>|<?https?://[^\s>]+>?").unwrap();
+    static ref RE_CODE_BLOCK: Regex = Regex::new(r"(?s)```.*?(```|$)").unwrap();
+    static ref RE_INLINE_CODE: Regex = Regex::new(r"`[^`]+`").unwrap();
+    // `>>> ` quotes everything until the end of the message
+    static ref RE_QUOTE: Regex = Regex::new(r"(?ms)^>>> .*|^> .*?$").unwrap();
+    static ref RE_SPOILER: Regex = Regex::new(r"(?s)\|\|.*?\|\|").unwrap();
+    static ref RE_MASKED_LINK: Regex = Regex::new(r"\[([^\]]*)\]\((<?https?://[^\s)]+>?)\)").unwrap();
+    static ref RE_URL: Regex = Regex::new(r"<?https?://(?:www\.)?([^/\s:?#>]+)[^\s>]*>?").unwrap();
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UrlRule {
+    Keep,
+    /// Only keep the domain of the URL
+    Domain,
+    Drop,
+}
+
+/// How to handle markdown when tokenizing
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct MarkdownRules {
+    pub urls: UrlRule,
+    pub skip_code: bool,
+    pub skip_quotes: bool,
+    pub skip_spoilers: bool,
+}
+
+impl Default for MarkdownRules {
+    fn default() -> Self {
+        Self { urls: UrlRule::Domain, skip_code: true, skip_quotes: true, skip_spoilers: true }
+    }
 }
 
 fn is_capitalized(token: &str) -> bool {
@@ -25,8 +65,141 @@ fn trim(token: &str) -> &str {
         .trim_end_matches(CLOSING_PUNCT)
 }
 
-pub fn tokenize(text: String) -> Vec<String> {
-    RE_TOKEN.find_iter(&text)
-        .map(|token| smart_lower(trim(token.as_str())))
-        .collect_vec()
+fn strip_markdown(text: &str, rules: &MarkdownRules) -> String {
+    let mut text = text.to_string();
+    if rules.skip_code {
+        text = RE_CODE_BLOCK.replace_all(&text, GAP_MARK).into_owned();
+        text = RE_INLINE_CODE.replace_all(&text, GAP_MARK).into_owned();
+    }
+    if rules.skip_quotes {
+        text = RE_QUOTE.replace_all(&text, GAP_MARK).into_owned();
+    }
+    if rules.skip_spoilers {
+        text = RE_SPOILER.replace_all(&text, GAP_MARK).into_owned();
+    }
+    // keep the text of masked links, the link itself is handled like any other URL
+    text = RE_MASKED_LINK.replace_all(&text, "$1 $2").into_owned();
+    match rules.urls {
+        UrlRule::Keep => text,
+        UrlRule::Domain => RE_URL.replace_all(&text, |caps: &Captures| caps[1].to_string()).into_owned(),
+        UrlRule::Drop => RE_URL.replace_all(&text, GAP_MARK).into_owned(),
+    }
+}
+
+fn words(text: &str, collapse: bool) -> impl Iterator<Item = String> + '_ {
+    segment(text).into_iter()
+        .map(move |word| {
+            let word = smart_lower(trim(&word));
+            if collapse { collapse_repeats(&word) } else { word }
+        })
+        .filter(|word| !word.is_empty())
+}
+
+/// Splits a message into words, emojis, mentions and URLs (if they're kept), 
+/// with a GAP where markdown was skipped.  
+/// If collapse is true, letters repeated 3 times or more are shortened to 2.
+pub fn tokenize(text: String, rules: &MarkdownRules, collapse: bool) -> Vec<String> {
+    let text = normalize(&strip_markdown(&text, rules));
+    let mut res = Vec::new();
+    for (i, part) in text.split(GAP_MARK).enumerate() {
+        if i > 0 {
+            res.push(GAP.to_string());
+        }
+        res.extend(tokenize_part(part, collapse));
+    }
+    res
+}
+
+fn tokenize_part(text: &str, collapse: bool) -> Vec<String> {
+    let mut res = Vec::new();
+    let mut last = 0;
+    for atomic in RE_ATOMIC.find_iter(text) {
+        res.extend(words(&text[last..atomic.start()], collapse));
+        let token = atomic.as_str();
+        if token.contains("://") {
+            // URLs in <> don't get an embed
+            res.push(token.trim_start_matches('<').trim_end_matches('>').to_string());
+        } else {
+            res.push(token.to_string());
+        }
+        last = atomic.end();
+    }
+    res.extend(words(&text[last..], collapse));
+    res
+}
+
+/// Pairs each token with the key it's counted under: its stem if there's a stemmer, the token itself otherwise
+pub fn stem_tokens(tokens: Vec<String>, stemmer: Option<Stemmer>) -> Vec<(String, String)> {
+    tokens.into_iter().map(|token| match stemmer {
+        Some(stemmer) => (stemmer.stem(&token), token),
+        None => (token.clone(), token),
+    }).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tokenize, stem_tokens, MarkdownRules, UrlRule, GAP};
+    use crate::idiom::Stemmer;
+
+    fn tokens(text: &str) -> Vec<String> {
+        tokenize(text.to_string(), &MarkdownRules::default(), true)
+    }
+
+    fn tokens_with(text: &str, rules: MarkdownRules) -> Vec<String> {
+        tokenize(text.to_string(), &rules, true)
+    }
+
+    #[test]
+    fn skipped_markdown_leaves_a_gap() {
+        assert_eq!(tokens("ice `code` cream"), vec!["ice", GAP, "cream"]);
+        assert_eq!(tokens("ice ||spoiler|| cream"), vec!["ice", GAP, "cream"]);
+    }
+
+    #[test]
+    fn kept_text_has_no_gap() {
+        assert_eq!(tokens("ice cream"), vec!["ice", "cream"]);
+    }
+
+    #[test]
+    fn code_quotes_and_spoilers_are_skipped() {
+        assert_eq!(tokens("look ```\nlet x = 1;\n``` here"), vec!["look", GAP, "here"]);
+        assert_eq!(tokens("> quoted\nreply"), vec![GAP, "reply"]);
+        assert_eq!(tokens(">>> quoted\nstill quoted"), vec![GAP]);
+        assert_eq!(tokens("the end is ||secret||"), vec!["the", "end", "is", GAP]);
+    }
+
+    #[test]
+    fn markdown_can_be_kept() {
+        let rules = MarkdownRules { urls: UrlRule::Domain, skip_code: false, skip_quotes: false, skip_spoilers: false };
+        assert_eq!(tokens_with("`code` ||secret||", rules), vec!["code", "secret"]);
+    }
+
+    #[test]
+    fn emphasis_is_stripped() {
+        assert_eq!(tokens("**bold** _italic_ ~~gone~~"), vec!["bold", "italic", "gone"]);
+    }
+
+    #[test]
+    fn urls_follow_the_rule() {
+        let rules = |urls| MarkdownRules { urls, ..MarkdownRules::default() };
+        assert_eq!(tokens_with("see https://www.example.com/page", rules(UrlRule::Domain)), vec!["see", "example.com"]);
+        assert_eq!(tokens_with("see <https://example.com/page>", rules(UrlRule::Keep)), vec!["see", "https://example.com/page"]);
+        assert_eq!(tokens_with("see https://example.com/page", rules(UrlRule::Drop)), vec!["see", GAP]);
+    }
+
+    #[test]
+    fn masked_links_keep_their_text() {
+        assert_eq!(tokens("[the docs](https://example.com/docs)"), vec!["the", "docs", "example.com"]);
+    }
+
+    #[test]
+    fn emojis_and_mentions_are_kept_whole() {
+        assert_eq!(tokens("hi <@123> <:wave:456>!"), vec!["hi", "<@123>", "<:wave:456>"]);
+    }
+
+    #[test]
+    fn gaps_survive_stemming() {
+        let stemmed = stem_tokens(vec!["cats".to_string(), GAP.to_string()], Some(Stemmer::English));
+        assert_eq!(stemmed, vec![("cat".to_string(), "cats".to_string()), (GAP.to_string(), GAP.to_string())]);
+    }
 }
@@ -1,52 +1,267 @@
-use itertools::enumerate;
-const AGING: f32 = 0.999;
+use std::{collections::HashMap, hash::Hash};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
-pub struct TopFreqs<const S: usize, T: Eq + Default = usize> {
-    pub data: [(T, f32); S],
-    pub max: f32,
+/// A monitored entry, its count overestimates the real one by at most error
+struct Counter<T> {
+    entry: T,
+    count: f32,
+    error: f32,
 }
 
-impl<const S: usize, T: Eq + Default> TopFreqs<S, T> {
+/// Space-Saving sketch keeping track of the (at most) S most frequent entries.
+/// The counters form a min-heap so updates are O(log S).
+/// Counts overestimate the real ones by at most total/S,
+/// so any entry with a real count above total/S is guaranteed to be monitored.
+pub struct TopFreqs<const S: usize, T: Hash + Eq + Clone = usize> {
+    heap: Vec<Counter<T>>,
+    positions: HashMap<T, usize>,
+    total: f32,
+}
+
+impl<const S: usize, T: Hash + Eq + Clone> TopFreqs<S, T> {
     pub fn new() -> Self {
-        Self {
-            data: core::array::from_fn(|_| (T::default(), 0.)), max: 0.
-        }
+        Self { heap: Vec::new(), positions: HashMap::new(), total: 0. }
     }
 
+    /// The estimated count of the entry, 0 if it's not monitored
     pub fn get(&self, entry: &T) -> f32 {
-        if let Some((_, v)) = self.data.iter().find(|(key, _)| key == entry) {
-            *v
-        } else {
-            0.
+        self.positions.get(entry).map_or(0., |i| self.heap[*i].count)
+    }
+
+    /// How much the count of the entry may be overestimated
+    #[cfg(test)]
+    pub fn error(&self, entry: &T) -> f32 {
+        self.positions.get(entry).map_or(0., |i| self.heap[*i].error)
+    }
+
+    /// The sum of everything that was added
+    #[cfg(test)]
+    pub fn total(&self) -> f32 {
+        self.total
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (T, f32)> + '_ {
+        self.heap.iter().map(|counter| (counter.entry.clone(), counter.count))
+    }
+
+    fn swap(&mut self, i: usize, j: usize) {
+        self.heap.swap(i, j);
+        self.positions.insert(self.heap[i].entry.clone(), i);
+        self.positions.insert(self.heap[j].entry.clone(), j);
+    }
+
+    fn sift_up(&mut self, mut i: usize) {
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.heap[parent].count <= self.heap[i].count {
+                break;
+            }
+            self.swap(i, parent);
+            i = parent;
         }
     }
 
-    pub fn age(&mut self) {
-        self.data.iter_mut().for_each(|(_, v)| *v *= AGING);
-        self.max *= AGING;
+    fn sift_down(&mut self, mut i: usize) {
+        loop {
+            let smallest = [2*i + 1, 2*i + 2].into_iter()
+                .filter(|child| *child < self.heap.len())
+                .fold(i, |smallest, child|
+                    if self.heap[child].count < self.heap[smallest].count { child } else { smallest }
+                );
+            if smallest == i {
+                break;
+            }
+            self.swap(i, smallest);
+            i = smallest;
+        }
     }
 
     pub fn add(&mut self, entry: T, value: f32) {
-        let mut idx = None;
-        let mut min = value;
-        for (i, (key, v)) in enumerate(&self.data) {
-            if key == &entry {
-                idx = Some(i);
-                break
+        self.total += value;
+        if let Some(&i) = self.positions.get(&entry) {
+            self.heap[i].count += value;
+            self.sift_down(i);
+        } else if self.heap.len() < S {
+            self.positions.insert(entry.clone(), self.heap.len());
+            self.heap.push(Counter { entry, count: value, error: 0. });
+            self.sift_up(self.heap.len() - 1);
+        } else if S > 0 {
+            // the new entry takes the place of the least frequent one, and inherits its count as error
+            let min = self.heap[0].count;
+            self.positions.remove(&self.heap[0].entry);
+            self.positions.insert(entry.clone(), 0);
+            self.heap[0] = Counter { entry, count: min + value, error: min };
+            self.sift_down(0);
+        }
+    }
+
+    /// Multiplies every count by a positive factor, which keeps their order
+    pub fn scale(&mut self, factor: f32) {
+        self.heap.iter_mut().for_each(|counter| {
+            counter.count *= factor;
+            counter.error *= factor;
+        });
+        self.total *= factor;
+    }
+
+    /// Takes back a value that was added before, if the entry is still monitored
+    pub fn remove(&mut self, entry: &T, value: f32) {
+        let Some(&i) = self.positions.get(entry) else {
+            return;
+        };
+        self.total = (self.total - value).max(0.);
+        let counter = &mut self.heap[i];
+        counter.count -= value;
+        counter.error = counter.error.min(counter.count);
+        if counter.count > 0. {
+            self.sift_up(i);
+        } else {
+            let last = self.heap.len() - 1;
+            self.swap(i, last);
+            self.heap.pop();
+            self.positions.remove(entry);
+            if i < self.heap.len() {
+                self.sift_down(i);
+                self.sift_up(i);
             }
-            if min > *v {
-                idx = Some(i);
-                min = *v;
+        }
+    }
+}
+
+impl<const S: usize, T: Hash + Eq + Clone + Serialize> Serialize for TopFreqs<S, T> {
+    fn serialize<Ser: Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        let counters: Vec<_> = self.heap.iter()
+            .map(|counter| (&counter.entry, counter.count, counter.error))
+            .collect();
+        (counters, self.total).serialize(serializer)
+    }
+}
+
+impl<'de, const S: usize, T: Hash + Eq + Clone + Deserialize<'de>> Deserialize<'de> for TopFreqs<S, T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let mut res = Self::new();
+        let (counters, total) = <(Vec<(T, f32, f32)>, f32)>::deserialize(deserializer)?;
+        counters.into_iter().for_each(|(entry, count, error)| {
+            res.add(entry.clone(), count);
+            if let Some(&i) = res.positions.get(&entry) {
+                res.heap[i].error = error;
             }
+        });
+        res.total = total;
+        Ok(res)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use itertools::Itertools;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+    use super::TopFreqs;
+
+    /// A stream of entries with Zipf-like frequencies, like words in messages
+    fn zipf_stream(len: usize, vocabulary: usize) -> Vec<usize> {
+        let mut rng = StdRng::seed_from_u64(42);
+        let weights = (1..=vocabulary).map(|rank| 1. / rank as f64).collect_vec();
+        let total: f64 = weights.iter().sum();
+        (0..len).map(|_| {
+            let mut x = rng.gen::<f64>() * total;
+            weights.iter().position(|w| { x -= w; x <= 0. }).unwrap_or(vocabulary - 1) + 1
+        }).collect()
+    }
+
+    fn exact_counts(stream: &[usize]) -> HashMap<usize, f32> {
+        let mut counts = HashMap::new();
+        stream.iter().for_each(|entry| *counts.entry(*entry).or_insert(0.) += 1.);
+        counts
+    }
+
+    #[test]
+    fn counts_are_exact_when_everything_fits() {
+        let stream = zipf_stream(2_000, 50);
+        let mut freqs = TopFreqs::<100>::new();
+        stream.iter().for_each(|entry| freqs.add(*entry, 1.));
+        for (entry, count) in exact_counts(&stream) {
+            assert_eq!(freqs.get(&entry), count);
+            assert_eq!(freqs.error(&entry), 0.);
         }
-        if let Some(i) = idx {
-            if self.data[i].0 == entry {
-                self.data[i].1 += value;
-            } else {
-                self.age();
-                self.data[i] = (entry, value);
+    }
+
+    #[test]
+    fn estimates_are_within_bounds() {
+        let stream = zipf_stream(50_000, 5_000);
+        let mut freqs = TopFreqs::<200>::new();
+        stream.iter().for_each(|entry| freqs.add(*entry, 1.));
+        let exact = exact_counts(&stream);
+        let bound = freqs.total() / 200.;
+        for (entry, estimate) in freqs.iter() {
+            let real = exact[&entry];
+            assert!(estimate >= real);
+            assert!(estimate - freqs.error(&entry) <= real);
+            assert!(estimate - real <= bound);
+        }
+        // every heavy hitter is monitored
+        for (entry, count) in &exact {
+            if *count > bound {
+                assert!(freqs.get(entry) > 0.);
             }
-            self.max += self.max.max(self.data[i].1);
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn top_k_matches_exact_counts() {
+        let stream = zipf_stream(50_000, 5_000);
+        let mut freqs = TopFreqs::<200>::new();
+        stream.iter().for_each(|entry| freqs.add(*entry, 1.));
+        let exact = exact_counts(&stream);
+        let k = 20;
+        let top_exact = exact.iter()
+            .sorted_by(|(_, a), (_, b)| b.total_cmp(a))
+            .take(k)
+            .map(|(entry, _)| *entry)
+            .collect_vec();
+        let top_sketch = freqs.iter()
+            .sorted_by(|(_, a), (_, b)| b.total_cmp(a))
+            .take(k)
+            .map(|(entry, _)| entry)
+            .collect_vec();
+        assert_eq!(top_exact, top_sketch);
+    }
+
+    #[test]
+    fn a_single_new_entry_does_not_change_other_counts() {
+        let mut freqs = TopFreqs::<3>::new();
+        [1, 1, 1, 2, 2, 3].into_iter().for_each(|entry| freqs.add(entry, 1.));
+        freqs.add(4, 1.);
+        assert_eq!(freqs.get(&1), 3.);
+        assert_eq!(freqs.get(&2), 2.);
+        assert_eq!(freqs.get(&3), 0.);
+        assert_eq!(freqs.get(&4), 2.);
+        assert_eq!(freqs.error(&4), 1.);
+    }
+
+    #[test]
+    fn removing_takes_back_what_was_added() {
+        let mut freqs = TopFreqs::<10>::new();
+        [1, 1, 2, 3, 3, 3].into_iter().for_each(|entry| freqs.add(entry, 1.));
+        freqs.remove(&3, 1.);
+        freqs.remove(&2, 1.);
+        assert_eq!(freqs.get(&3), 2.);
+        assert_eq!(freqs.get(&2), 0.);
+        assert_eq!(freqs.iter().count(), 2);
+        assert_eq!(freqs.total(), 4.);
+    }
+
+    #[test]
+    fn serialization_roundtrip() {
+        let mut freqs = TopFreqs::<3>::new();
+        [1, 1, 1, 2, 2, 3, 4].into_iter().for_each(|entry| freqs.add(entry, 1.));
+        let json = serde_json::to_string(&freqs).unwrap();
+        let loaded: TopFreqs<3> = serde_json::from_str(&json).unwrap();
+        for entry in 1..=4 {
+            assert_eq!(loaded.get(&entry), freqs.get(&entry));
+            assert_eq!(loaded.error(&entry), freqs.error(&entry));
+        }
+        assert_eq!(loaded.total(), freqs.total());
+    }
+}
@@ -5,7 +5,9 @@ mod discord_util;
 mod wordy;
 mod wordy_events;
 mod wordy_commands;
-mod fixed_deque;
+mod timed_counts;
+mod storage;
+mod config;
 use wordy::Wordy;
 use env_logger;
 use std::fs::read_to_string;
@@ -39,6 +41,12 @@ async fn main() {
         .init();
     // Configure the client with your Discord bot token in the environment.
     let token = get_token("WORDY_TOKEN").unwrap();
+    // japanese is split by scripts unless we're given a dictionary for it
+    if let Ok(path) = env::var("WORDY_JAPANESE_DICTIONARY") {
+        if let Err(why) = idiom::load_japanese_dictionary(&path) {
+            warn!(target: "wordy", "{:?}", why);
+        }
+    }
     let http = Http::new(&token);
 
     // The Application Id is usually the Bot User Id.
@@ -46,13 +54,15 @@ async fn main() {
         Ok(info) => info.id,
         Err(why) => panic!("Could not access application info: {:?}", why),
     };
+    let wordy = Wordy::new();
+    wordy.autosave();
     // Build our client.
     let mut client = Client::builder(
         token, GatewayIntents::non_privileged()
         | GatewayIntents::GUILD_MEMBERS
         | GatewayIntents::GUILD_PRESENCES
     )
-        .event_handler(Wordy::new())
+        .event_handler(wordy)
         .application_id(bot_id.into())
         .await
         .expect("Error creating client");
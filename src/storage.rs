@@ -0,0 +1,100 @@
+use std::{collections::{HashMap, HashSet}, fs, path::{Path, PathBuf}};
+use anyhow::{Result, Context};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serenity::model::prelude::{ChannelId, EmojiId, GuildId, MessageId, StickerId, UserId};
+use crate::{idiom::Idioms, timed_counts::TimedCounts};
+
+/// Borrowed view of a guild's state, so we don't have to clone it to save it
+#[derive(Serialize)]
+pub struct GuildSnapshotRef<'a> {
+    pub idioms: &'a Idioms<ChannelId, UserId>,
+    pub recent_emos: &'a TimedCounts<EmojiId>,
+    pub user_emos: &'a HashMap<UserId, TimedCounts<EmojiId>>,
+    pub reactions: &'a TimedCounts<EmojiId>,
+    pub external_emos: &'a TimedCounts<String>,
+    pub recent_stickers: &'a TimedCounts<StickerId>,
+    pub last_reads: &'a HashMap<ChannelId, MessageId>,
+}
+
+#[derive(Deserialize)]
+pub struct GuildSnapshot {
+    pub idioms: Idioms<ChannelId, UserId>,
+    pub recent_emos: TimedCounts<EmojiId>,
+    /// Server emojis used in the messages of each user
+    pub user_emos: HashMap<UserId, TimedCounts<EmojiId>>,
+    /// Server emojis used as reactions, at the time of the message they react to
+    pub reactions: TimedCounts<EmojiId>,
+    /// Emojis from other servers, by how they are written in messages
+    pub external_emos: TimedCounts<String>,
+    pub recent_stickers: TimedCounts<StickerId>,
+    /// Id of the newest message read in each channel
+    pub last_reads: HashMap<ChannelId, MessageId>,
+}
+
+pub struct Storage {
+    dir: PathBuf,
+}
+
+impl Storage {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    fn guild_path(&self, guild_id: GuildId) -> PathBuf {
+        self.dir.join(format!("{}.json", guild_id))
+    }
+
+    fn optouts_path(&self) -> PathBuf {
+        self.dir.join("optouts.json")
+    }
+
+    /// Writes on a blocking thread so a big save doesn't hold up the async runtime
+    async fn write(&self, path: PathBuf, content: Vec<u8>) -> Result<()> {
+        let dir = self.dir.clone();
+        tokio::task::spawn_blocking(move || {
+            fs::create_dir_all(&dir).context("Couldn't create the data folder")?;
+            // write to a temporary file first so a crash mid-write doesn't corrupt the previous save
+            let tmp_path = path.with_extension("json.tmp");
+            fs::write(&tmp_path, content)?;
+            fs::rename(&tmp_path, &path)?;
+            Ok(())
+        }).await?
+    }
+
+    fn load<T: DeserializeOwned>(&self, path: &Path) -> Result<Option<T>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = fs::read(path)?;
+        Ok(Some(serde_json::from_slice(&content).with_context(|| format!("Corrupted save {:?}", path))?))
+    }
+
+    /// The snapshot is serialized beforehand, so the guild's state doesn't stay locked while it's written
+    pub fn serialize_guild(snapshot: &GuildSnapshotRef) -> Result<Vec<u8>> {
+        Ok(serde_json::to_vec(snapshot)?)
+    }
+
+    pub async fn save_guild(&self, guild_id: GuildId, content: Vec<u8>) -> Result<()> {
+        self.write(self.guild_path(guild_id), content).await
+    }
+
+    pub fn load_guild(&self, guild_id: GuildId) -> Result<Option<GuildSnapshot>> {
+        self.load(&self.guild_path(guild_id))
+    }
+
+    pub fn delete_guild(&self, guild_id: GuildId) -> Result<()> {
+        let path = self.guild_path(guild_id);
+        if path.exists() {
+            fs::remove_file(path)?;
+        }
+        Ok(())
+    }
+
+    pub async fn save_optouts(&self, optouts: &HashSet<UserId>) -> Result<()> {
+        self.write(self.optouts_path(), serde_json::to_vec(optouts)?).await
+    }
+
+    pub fn load_optouts(&self) -> Result<HashSet<UserId>> {
+        Ok(self.load(&self.optouts_path())?.unwrap_or_default())
+    }
+}
@@ -0,0 +1,130 @@
+use std::{collections::{BTreeMap, HashMap}, hash::Hash};
+use serde::{Deserialize, Serialize};
+const DAY: i64 = 3600 * 24;
+
+/// Daily counts of uses, so they can be looked at over any number of days
+#[derive(Serialize, Deserialize)]
+#[serde(bound(serialize = "K: Serialize", deserialize = "K: Deserialize<'de> + Hash + Eq"))]
+pub struct TimedCounts<K: Hash + Eq> {
+    days: BTreeMap<i64, HashMap<K, u32>>,
+}
+
+impl<K: Hash + Eq + Clone> TimedCounts<K> {
+    pub fn new() -> Self {
+        Self { days: BTreeMap::new() }
+    }
+
+    /// Counts a use at this unix timestamp
+    pub fn add(&mut self, key: K, time: i64) {
+        self.add_many(key, 1, time);
+    }
+
+    /// Counts several uses at this unix timestamp
+    pub fn add_many(&mut self, key: K, uses: u32, time: i64) {
+        if uses > 0 {
+            *self.days.entry(time.div_euclid(DAY)).or_default().entry(key).or_default() += uses;
+        }
+    }
+
+    /// Takes back a use that was added before
+    pub fn remove(&mut self, key: &K, time: i64) {
+        self.remove_many(key, 1, time);
+    }
+
+    /// Takes back several uses that were added before
+    pub fn remove_many(&mut self, key: &K, uses: u32, time: i64) {
+        let day = time.div_euclid(DAY);
+        if let Some(counts) = self.days.get_mut(&day) {
+            if let Some(count) = counts.get_mut(key) {
+                *count = count.saturating_sub(uses);
+                if *count == 0 {
+                    counts.remove(key);
+                }
+            }
+            if counts.is_empty() {
+                self.days.remove(&day);
+            }
+        }
+    }
+
+    /// Forgets the uses older than this unix timestamp
+    pub fn prune(&mut self, oldest: i64) {
+        while self.days.first_key_value().is_some_and(|(day, _)| *day < oldest.div_euclid(DAY)) {
+            self.days.pop_first();
+        }
+    }
+
+    /// Uses since this unix timestamp, or since forever if None
+    pub fn counts(&self, start: Option<i64>) -> HashMap<K, u32> {
+        self.counts_between(start.unwrap_or(i64::MIN), i64::MAX)
+    }
+
+    /// Uses from the day of start until the day of end, excluded, both unix timestamps
+    pub fn counts_between(&self, start: i64, end: i64) -> HashMap<K, u32> {
+        let mut res = HashMap::new();
+        self.days.range(start.div_euclid(DAY)..end.div_euclid(DAY))
+            .flat_map(|(_, counts)| counts)
+            .for_each(|(key, count)| *res.entry(key.clone()).or_default() += count);
+        res
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use super::{TimedCounts, DAY};
+
+    /// One use of "a" on day 1, two of "b" on day 2 and one of each on day 3, in the middle of the days
+    fn counts() -> TimedCounts<&'static str> {
+        let mut counts = TimedCounts::new();
+        counts.add("a", DAY + DAY / 2);
+        counts.add_many("b", 2, 2 * DAY + DAY / 2);
+        counts.add("a", 3 * DAY + DAY / 2);
+        counts.add("b", 3 * DAY + DAY / 2);
+        counts
+    }
+
+    #[test]
+    fn counts_between_excludes_the_end_day() {
+        let counts = counts();
+        assert_eq!(counts.counts_between(DAY, 3 * DAY), HashMap::from([("a", 1), ("b", 2)]));
+        // the whole day of start is counted, none of the day of end
+        assert_eq!(counts.counts_between(2 * DAY - 1, 3 * DAY + DAY / 2), HashMap::from([("a", 1), ("b", 2)]));
+        assert_eq!(counts.counts_between(2 * DAY + DAY / 2, 3 * DAY), HashMap::from([("b", 2)]));
+        assert!(counts.counts_between(2 * DAY, 2 * DAY + DAY / 2).is_empty());
+    }
+
+    #[test]
+    fn counts_start_at_the_day_of_start() {
+        let counts = counts();
+        assert_eq!(counts.counts(None), HashMap::from([("a", 2), ("b", 3)]));
+        assert_eq!(counts.counts(Some(2 * DAY + DAY - 1)), HashMap::from([("a", 1), ("b", 3)]));
+        assert_eq!(counts.counts(Some(3 * DAY)), HashMap::from([("a", 1), ("b", 1)]));
+        assert!(counts.counts(Some(4 * DAY)).is_empty());
+    }
+
+    #[test]
+    fn prune_keeps_the_day_of_oldest() {
+        let mut counts = counts();
+        counts.prune(2 * DAY + DAY - 1);
+        assert_eq!(counts.counts(None), HashMap::from([("a", 1), ("b", 3)]));
+        counts.prune(5 * DAY);
+        assert!(counts.days.is_empty());
+    }
+
+    #[test]
+    fn removing_every_use_drops_the_entry() {
+        let mut counts = counts();
+        counts.remove(&"a", DAY);
+        assert!(!counts.days.contains_key(&1));
+        counts.remove_many(&"b", 1, 2 * DAY);
+        assert_eq!(counts.days[&2], HashMap::from([("b", 1)]));
+        // removing more than was counted stops at 0
+        counts.remove_many(&"b", 5, 3 * DAY);
+        assert_eq!(counts.days[&3], HashMap::from([("a", 1)]));
+        // uses that were never counted are ignored
+        counts.remove(&"c", 3 * DAY);
+        counts.remove(&"a", 10 * DAY);
+        assert_eq!(counts.counts(None), HashMap::from([("a", 1), ("b", 1)]));
+    }
+}
@@ -1,20 +1,28 @@
 use itertools::Itertools;
-use log::{warn, info, trace};
+use log::{warn, info, debug, trace};
 use image::RgbaImage;
 use regex::Regex;
-use std::{sync::Arc, collections::HashMap};
-use anyhow::{Result, bail};
-use palette::rgb::Rgb;
-use dashmap::DashMap;
+use std::{sync::Arc, cmp::Reverse, collections::{hash_map::Entry, HashMap, HashSet}, hash::Hash, time::Duration};
+use anyhow::{Result, bail, anyhow};
+use palette::{rgb::Rgb, FromColor, Hsv};
+use dashmap::{DashMap, DashSet};
+use moka::{sync::Cache, ops::compute::Op};
 use serenity::{
-    all::Color, http::Http, model:: {
-        id::GuildId, prelude::{Channel, ChannelId, Emoji, EmojiId, Guild, Member, Message, UserId}
+    all::Color, cache::Cache as SerenityCache, http::Http, model:: {
+        Timestamp, id::GuildId, prelude::{Channel, ChannelId, Emoji, EmojiId, Guild, Member, Message, MessageId, MessageReaction, Reaction, ReactionType, Role, Sticker, StickerId, UserId}
     }, prelude::*
 };
 use futures::future::join_all;
+use tokio::task::AbortHandle;
 use lazy_static::lazy_static;
 use wordcloud_rs::{Token, WordCloud, Colors};
-use crate::{idiom::{Idioms, tokenize}, discord_emojis::DiscordEmojis, fixed_deque::FixedDeque, emoji_usage::EmojiUsage};
+use crate::{
+    idiom::{Idioms, Period, tokenize, stem_tokens, detect_lang, remove_stopwords, is_emoji}, discord_emojis::DiscordEmojis, timed_counts::TimedCounts, emoji_usage::{EmojiUsage, EmojiSource, StickerUsage, EmojiReport, ReportEntry},
+    storage::{Storage, GuildSnapshotRef}, config::{ConfigStore, GuildConfig}, discord_util::cached_lineage
+};
+const AUTOSAVE_PERIOD: Duration = Duration::from_secs(600);
+// How many messages we remember the contributions of, to handle edits and deletions
+const MESSAGE_TRACES: u64 = 100_000;
 
 lazy_static! {
     static ref RE_EMO: Regex = Regex::new(r"^<a?:(\w+):(\d+)>$").unwrap();
@@ -23,17 +31,78 @@ lazy_static! {
     static ref RE_ROLE: Regex = Regex::new(r"^<@&(\d+)>$").unwrap();
 }
 
+/// Whose word cloud to generate
+pub enum CloudSubject {
+    Member(Box<Member>),
+    Channel(ChannelId, String),
+    Role(Role),
+    Server,
+}
+
+impl CloudSubject {
+    pub fn name(&self) -> String {
+        match self {
+            CloudSubject::Member(member) => member.display_name().to_string(),
+            CloudSubject::Channel(_, name) => format!("#{}", name),
+            CloudSubject::Role(role) => format!("@{}", role.name),
+            CloudSubject::Server => "Server".to_string(),
+        }
+    }
+}
+
+/// What a message added to the idioms, so we can take it back if it gets edited or deleted
+#[derive(Clone)]
+pub struct MessageTrace {
+    pub guild_id: GuildId,
+    pub channel_id: ChannelId,
+    pub author_id: UserId,
+    /// Unix timestamp of the message, which its tokens are weighted by
+    pub timestamp: i64,
+    pub tokens: Vec<usize>,
+    /// The server emojis of the message
+    pub emojis: Vec<EmojiId>,
+    /// The server stickers of the message
+    pub stickers: Vec<StickerId>,
+    /// The emojis from other servers of the message
+    pub external_emojis: Vec<String>,
+    /// How many times each server emoji was counted as a reaction to the message
+    pub reactions: HashMap<EmojiId, u32>,
+    /// How many times each emoji from other servers was counted as a reaction to the message
+    pub external_reactions: HashMap<String, u32>,
+}
+
+/// What the backfill of a guild already read, so that live messages and the backfill don't count the same messages twice
+pub struct CatchUp {
+    /// When the backfill started, channels created after that have no history to read
+    pub since: Timestamp,
+    /// Channels whose history was read, where live messages can move the last read
+    pub done: HashSet<ChannelId>,
+    /// First and last live messages counted in the channels whose history wasn't read yet
+    pub live: HashMap<ChannelId, (MessageId, MessageId)>,
+}
+
+impl CatchUp {
+    pub fn new(live: HashMap<ChannelId, (MessageId, MessageId)>) -> Self {
+        Self { since: Timestamp::now(), done: HashSet::new(), live }
+    }
+
+    pub fn caught_up(&self, channel_id: ChannelId) -> bool {
+        self.done.contains(&channel_id) || channel_id.created_at() > self.since
+    }
+}
+
 pub struct EmojiRankings {
     pub png: Vec<EmojiUsage>,
     pub gif: Vec<EmojiUsage>
 }
 
-fn norm_emo_ranking(emo_ranking: Vec<(Emoji, usize)>) -> Vec<EmojiUsage> {
+fn norm_emo_ranking<T>(emo_ranking: Vec<(T, u32)>) -> Vec<EmojiUsage<T>> {
     let sum = emo_ranking.iter().fold(
         0., |acc, (_, count)| *count as f64 + acc
     );
     emo_ranking.into_iter().map(
-        |(emoji_id, count)| EmojiUsage(emoji_id, count as f64/sum)
+        // avoid NaN when no emoji was used
+        |(emoji_id, count)| EmojiUsage(emoji_id, count, count as f64/sum.max(1.))
     ).collect()
 }
 
@@ -45,56 +114,273 @@ fn convert_color(color: Color) -> Rgb {
     )
 }
 
+/// A hue that is unique to the channel, so that its cloud always gets the same color
+fn channel_color(channel_id: ChannelId) -> Rgb {
+    let hue = (channel_id.get() >> 22) % 360;
+    Rgb::from_color(Hsv::new(hue as f32, 0.6, 1.))
+}
+
+/// Registers the guild, restoring its state from the storage if there is a snapshot of it.
+#[allow(clippy::too_many_arguments)]
 pub fn register_guild(
     guild: &Guild,
     idioms: Arc<DashMap<GuildId, Idioms<ChannelId, UserId>>>, 
-    recents_emos: Arc<DashMap<GuildId, FixedDeque<EmojiId>>>,
+    recents_emos: Arc<DashMap<GuildId, TimedCounts<EmojiId>>>,
+    users_emos: Arc<DashMap<GuildId, HashMap<UserId, TimedCounts<EmojiId>>>>,
+    reactions: Arc<DashMap<GuildId, TimedCounts<EmojiId>>>,
+    externals_emos: Arc<DashMap<GuildId, TimedCounts<String>>>,
     servers_emos: Arc<DashMap<GuildId, HashMap<EmojiId, Emoji>>>,
-) -> bool {
+    recents_stickers: Arc<DashMap<GuildId, TimedCounts<StickerId>>>,
+    servers_stickers: Arc<DashMap<GuildId, HashMap<StickerId, Sticker>>>,
+    last_reads: Arc<DashMap<GuildId, HashMap<ChannelId, MessageId>>>,
+    storage: &Storage,
+    half_life: Option<f32>,
+) {
     if !idioms.contains_key(&guild.id) {
-        info!(target: "wordy", "Registering {} (id {})", guild.name, guild.id);
-        idioms.insert(guild.id, Idioms::new());
-        recents_emos.insert(guild.id, FixedDeque::new());
         servers_emos.insert(guild.id, guild.emojis.clone());
-        true
+        servers_stickers.insert(guild.id, guild.stickers.clone());
+        match storage.load_guild(guild.id) {
+            Ok(Some(snapshot)) => {
+                info!(target: "wordy", "Restoring {} (id {}) from storage", guild.name, guild.id);
+                idioms.insert(guild.id, snapshot.idioms);
+                recents_emos.insert(guild.id, snapshot.recent_emos);
+                users_emos.insert(guild.id, snapshot.user_emos);
+                reactions.insert(guild.id, snapshot.reactions);
+                externals_emos.insert(guild.id, snapshot.external_emos);
+                recents_stickers.insert(guild.id, snapshot.recent_stickers);
+                last_reads.insert(guild.id, snapshot.last_reads);
+                return;
+            },
+            Ok(None) => {},
+            Err(why) => warn!(target: "wordy", "Couldn't restore {} (id {}): {:?}", guild.name, guild.id, why)
+        }
+        info!(target: "wordy", "Registering {} (id {})", guild.name, guild.id);
+        idioms.insert(guild.id, Idioms::new(half_life));
+        recents_emos.insert(guild.id, TimedCounts::new());
+        users_emos.insert(guild.id, HashMap::new());
+        reactions.insert(guild.id, TimedCounts::new());
+        externals_emos.insert(guild.id, TimedCounts::new());
+        recents_stickers.insert(guild.id, TimedCounts::new());
+        last_reads.insert(guild.id, HashMap::new());
     } else {
         info!(target: "wordy", "Guild {} (id {}) was already registered", guild.name, guild.id);
-        false
     }
 }
 
+/// Serializes the guild under its locks, and only writes it once they are released
+#[allow(clippy::too_many_arguments)]
+pub async fn save_guild(
+    guild_id: GuildId,
+    idioms: &DashMap<GuildId, Idioms<ChannelId, UserId>>, 
+    recents_emos: &DashMap<GuildId, TimedCounts<EmojiId>>,
+    users_emos: &DashMap<GuildId, HashMap<UserId, TimedCounts<EmojiId>>>,
+    reactions: &DashMap<GuildId, TimedCounts<EmojiId>>,
+    externals_emos: &DashMap<GuildId, TimedCounts<String>>,
+    recents_stickers: &DashMap<GuildId, TimedCounts<StickerId>>,
+    last_reads: &DashMap<GuildId, HashMap<ChannelId, MessageId>>,
+    storage: &Storage,
+) -> Result<()> {
+    let content = if let (
+        Some(idiom),
+        Some(recent_emos),
+        Some(user_emos),
+        Some(reactions),
+        Some(external_emos),
+        Some(recent_stickers),
+        Some(last_read)
+    ) = (
+        idioms.get(&guild_id),
+        recents_emos.get(&guild_id),
+        users_emos.get(&guild_id),
+        reactions.get(&guild_id),
+        externals_emos.get(&guild_id),
+        recents_stickers.get(&guild_id),
+        last_reads.get(&guild_id)
+    ) {
+        Storage::serialize_guild(&GuildSnapshotRef { 
+            idioms: &idiom, 
+            recent_emos: &recent_emos, 
+            user_emos: &user_emos,
+            reactions: &reactions,
+            external_emos: &external_emos,
+            recent_stickers: &recent_stickers,
+            last_reads: &last_read 
+        })?
+    } else {
+        bail!("Guild is not yet registered")
+    };
+    storage.save_guild(guild_id, content).await
+}
+
+/// The server emojis among the tokens, once each
+fn server_emojis(tokens: &[(String, String)], server_emos: &HashMap<EmojiId, Emoji>) -> Vec<EmojiId> {
+    tokens.iter()
+        .filter_map(|(token, _)| {
+            let caps = RE_EMO.captures(token)?;
+            let emoji_id = EmojiId::new(caps.get(2).unwrap().as_str().parse::<u64>().unwrap());
+            server_emos.contains_key(&emoji_id).then_some(emoji_id)
+        })
+        .unique()
+        .collect()
+}
+
+/// The emojis from other servers among the tokens, once each, as they are written in messages
+fn external_emojis(tokens: &[(String, String)], server_emos: &HashMap<EmojiId, Emoji>) -> Vec<String> {
+    tokens.iter()
+        .filter(|(token, _)| RE_EMO.captures(token).is_some_and(|caps| 
+            !server_emos.contains_key(&EmojiId::new(caps.get(2).unwrap().as_str().parse::<u64>().unwrap()))
+        ))
+        .map(|(token, _)| token.clone())
+        .unique()
+        .collect()
+}
+
+/// How the emoji of a reaction is written in messages, None if it's not a custom emoji 
+/// or if it has no name, which happens when it was deleted
+fn reaction_markup(reaction: &ReactionType) -> Option<String> {
+    match reaction {
+        ReactionType::Custom { animated, id, name: Some(name) } => Some(format!(
+            "<{}:{}:{}>", if *animated { "a" } else { "" }, name, id
+        )),
+        _ => None
+    }
+}
+
+/// Counts one more or one less use of the key, forgetting it when it reaches 0
+fn update_count<K: Eq + Hash>(counts: &mut HashMap<K, u32>, key: K, added: bool) {
+    if added {
+        *counts.entry(key).or_default() += 1;
+    } else if let Entry::Occupied(mut count) = counts.entry(key) {
+        *count.get_mut() -= 1;
+        if *count.get() == 0 {
+            count.remove();
+        }
+    }
+}
+
+/// The server emojis in the reactions, with how many times each was used
+fn server_reactions(reactions: &[MessageReaction], server_emos: &HashMap<EmojiId, Emoji>) -> Vec<(EmojiId, u32)> {
+    reactions.iter()
+        .filter_map(|reaction| match reaction.reaction_type {
+            ReactionType::Custom { id, .. } if server_emos.contains_key(&id) => Some((id, reaction.count as u32)),
+            _ => None
+        })
+        .collect()
+}
+
+/// The emojis from other servers in the reactions, with how many times each was used
+fn external_reactions(reactions: &[MessageReaction], server_emos: &HashMap<EmojiId, Emoji>) -> Vec<(String, u32)> {
+    reactions.iter()
+        .filter_map(|reaction| match reaction.reaction_type {
+            ReactionType::Custom { id, .. } if server_emos.contains_key(&id) => None,
+            _ => Some((reaction_markup(&reaction.reaction_type)?, reaction.count as u32)),
+        })
+        .collect()
+}
+
+/// Turns the content of a message into the (key, surface form) pairs we feed to the idioms
+fn words(content: String, config: &GuildConfig) -> Vec<(String, String)> {
+    let tokens = tokenize(content, &config.markdown, config.collapse_repeats);
+    // the language is told from what's left once the markdown is skipped, 
+    // and short messages are often undetectable, in which case we use the language the server stems words in
+    let lang = detect_lang(&tokens.iter().filter(|token| !token.is_empty()).join(" "))
+        .or(config.stemmer.map(|stemmer| stemmer.lang()));
+    stem_tokens(remove_stopwords(tokens, lang, &config.stopwords), config.stemmer)
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn read_message(
     guild_id: GuildId,
     message: Message, 
     idioms: Arc<DashMap<GuildId, Idioms<ChannelId, UserId>>>, 
-    recents_emos: Arc<DashMap<GuildId, FixedDeque<EmojiId>>>,
+    recents_emos: Arc<DashMap<GuildId, TimedCounts<EmojiId>>>,
+    users_emos: Arc<DashMap<GuildId, HashMap<UserId, TimedCounts<EmojiId>>>>,
+    reactions: Arc<DashMap<GuildId, TimedCounts<EmojiId>>>,
+    externals_emos: Arc<DashMap<GuildId, TimedCounts<String>>>,
     servers_emos: Arc<DashMap<GuildId, HashMap<EmojiId, Emoji>>>,
+    recents_stickers: Arc<DashMap<GuildId, TimedCounts<StickerId>>>,
+    servers_stickers: Arc<DashMap<GuildId, HashMap<StickerId, Sticker>>>,
+    traces: Cache<MessageId, MessageTrace>,
+    optouts: Arc<DashSet<UserId>>,
+    config: &GuildConfig,
+    words_only: bool,
 ) {
+    if optouts.contains(&message.author.id) {
+        return;
+    }
     if let (
         Some(mut idiom), 
         Some(mut recent_emos),
-        Some(server_emos)
+        Some(mut user_emos),
+        Some(mut guild_reactions),
+        Some(mut external_emos),
+        Some(server_emos),
+        Some(mut recent_stickers),
+        Some(server_stickers),
     ) = (
         idioms.get_mut(&guild_id), 
         recents_emos.get_mut(&guild_id),
-        servers_emos.get(&guild_id)
+        users_emos.get_mut(&guild_id),
+        reactions.get_mut(&guild_id),
+        externals_emos.get_mut(&guild_id),
+        servers_emos.get(&guild_id),
+        recents_stickers.get_mut(&guild_id),
+        servers_stickers.get(&guild_id),
     ) {
-        let tokens = tokenize(message.content);
-        tokens
-        .iter()
-        .filter_map(|token| {
-            if let Some(caps) = RE_EMO.captures(token) {
-                let emoji_id = EmojiId::new(
-                    caps.get(2).unwrap().as_str().parse::<u64>().unwrap()
-                );
-                if server_emos.contains_key(&emoji_id) {
-                    return Some(emoji_id);
+        let timestamp = message.timestamp.unix_timestamp();
+        let tokens = words(message.content, config);
+        let emojis = server_emojis(&tokens, &server_emos);
+        let external_emojis = external_emojis(&tokens, &server_emos);
+        let stickers = message.sticker_items.iter()
+            .map(|sticker| sticker.id)
+            .filter(|sticker_id| server_stickers.contains_key(sticker_id))
+            .collect_vec();
+        let mut counted_reactions = HashMap::new();
+        let mut counted_external_reactions = HashMap::new();
+        if config.emoji_cutoff().is_none_or(|oldest| timestamp >= oldest) {
+            counted_reactions.extend(server_reactions(&message.reactions, &server_emos));
+            counted_external_reactions.extend(external_reactions(&message.reactions, &server_emos));
+            // the emojis of a message that is read again were counted the first time
+            if !words_only {
+                let author_emos = user_emos.entry(message.author.id).or_insert_with(TimedCounts::new);
+                if let Some(oldest) = config.emoji_cutoff() {
+                    recent_emos.prune(oldest);
+                    author_emos.prune(oldest);
+                    guild_reactions.prune(oldest);
+                    external_emos.prune(oldest);
+                    recent_stickers.prune(oldest);
                 }
+                emojis.iter().for_each(|emoji_id| {
+                    recent_emos.add(*emoji_id, timestamp);
+                    author_emos.add(*emoji_id, timestamp);
+                });
+                stickers.iter().for_each(|sticker_id| recent_stickers.add(*sticker_id, timestamp));
+                // reactions are counted at the time of the message, so removing one later takes back the right day
+                counted_reactions.iter()
+                    .for_each(|(emoji_id, count)| guild_reactions.add_many(*emoji_id, *count, timestamp));
+                external_emojis.iter().for_each(|emoji| external_emos.add(emoji.clone(), timestamp));
+                counted_external_reactions.iter()
+                    .for_each(|(emoji, count)| external_emos.add_many(emoji.clone(), *count, timestamp));
             }
-            None
-        }).unique()
-        .for_each(|emoji_id| recent_emos.push(emoji_id.clone()));
-        idiom.update(message.channel_id, message.author.id, tokens);
+        }
+        let indices = idiom.update(message.channel_id, message.author.id, tokens, timestamp);
+        let trace = match words_only.then(|| traces.get(&message.id)).flatten() {
+            // the trace also knows about the reactions that came after the message was first read
+            Some(trace) => MessageTrace { tokens: indices, ..trace },
+            None => MessageTrace { 
+                guild_id, 
+                channel_id: message.channel_id, 
+                author_id: message.author.id, 
+                timestamp,
+                tokens: indices,
+                emojis,
+                stickers,
+                external_emojis,
+                reactions: counted_reactions,
+                external_reactions: counted_external_reactions,
+            }
+        };
+        traces.insert(message.id, trace);
     } else {
         warn!(target: "wordy", "Guild {} isn't registered yet.", guild_id);
     }
@@ -103,30 +389,416 @@ pub fn read_message(
 pub struct Wordy {
     pub idioms: Arc<DashMap<GuildId, Idioms<ChannelId, UserId>>>,
     pub discord_emos: DiscordEmojis,
-    pub recents_emos: Arc<DashMap<GuildId, FixedDeque<EmojiId>>>,
+    pub recents_emos: Arc<DashMap<GuildId, TimedCounts<EmojiId>>>,
+    /// Server emojis used in the messages of each user
+    pub users_emos: Arc<DashMap<GuildId, HashMap<UserId, TimedCounts<EmojiId>>>>,
+    /// Server emojis used as reactions
+    pub reactions: Arc<DashMap<GuildId, TimedCounts<EmojiId>>>,
+    /// Emojis from other servers, used in messages or as reactions, by how they are written
+    pub externals_emos: Arc<DashMap<GuildId, TimedCounts<String>>>,
     pub servers_emos: Arc<DashMap<GuildId, HashMap<EmojiId, Emoji>>>,
+    pub recents_stickers: Arc<DashMap<GuildId, TimedCounts<StickerId>>>,
+    pub servers_stickers: Arc<DashMap<GuildId, HashMap<StickerId, Sticker>>>,
+    pub last_reads: Arc<DashMap<GuildId, HashMap<ChannelId, MessageId>>>,
+    pub storage: Arc<Storage>,
+    /// The backfill task of each guild, so we can cancel them
+    pub backfills: DashMap<GuildId, AbortHandle>,
+    pub catch_ups: Arc<DashMap<GuildId, CatchUp>>,
+    pub traces: Cache<MessageId, MessageTrace>,
+    /// Users who don't want their messages to be read
+    pub optouts: Arc<DashSet<UserId>>,
+    pub configs: DashMap<GuildId, GuildConfig>,
+    pub config_store: ConfigStore,
 }
 
 impl Wordy {
     pub fn new() -> Self {
+        let storage = Storage::new("data");
+        let optouts = storage.load_optouts().unwrap_or_else(|why| {
+            warn!(target: "wordy", "Couldn't load the opt-outs: {:?}", why);
+            HashSet::new()
+        });
+        let config_store = ConfigStore::new("config.toml");
+        let configs = config_store.load().unwrap_or_else(|why| {
+            warn!(target: "wordy", "Couldn't load the config: {:?}", why);
+            HashMap::new()
+        });
         Self {
             idioms: Arc::new(DashMap::new()),
             discord_emos: DiscordEmojis::new(1000),
             recents_emos: Arc::new(DashMap::new()),
+            users_emos: Arc::new(DashMap::new()),
+            reactions: Arc::new(DashMap::new()),
+            externals_emos: Arc::new(DashMap::new()),
             servers_emos: Arc::new(DashMap::new()),
+            recents_stickers: Arc::new(DashMap::new()),
+            servers_stickers: Arc::new(DashMap::new()),
+            last_reads: Arc::new(DashMap::new()),
+            storage: Arc::new(storage),
+            backfills: DashMap::new(),
+            catch_ups: Arc::new(DashMap::new()),
+            traces: Cache::new(MESSAGE_TRACES),
+            optouts: Arc::new(optouts.into_iter().collect()),
+            configs: configs.into_iter().collect(),
+            config_store,
         }
     }
 
-    pub fn message(&self, message: Message) {
+    /// Periodically saves every registered guild to the storage
+    pub fn autosave(&self) {
+        let idioms = Arc::clone(&self.idioms);
+        let recents_emos = Arc::clone(&self.recents_emos);
+        let users_emos = Arc::clone(&self.users_emos);
+        let reactions = Arc::clone(&self.reactions);
+        let externals_emos = Arc::clone(&self.externals_emos);
+        let recents_stickers = Arc::clone(&self.recents_stickers);
+        let last_reads = Arc::clone(&self.last_reads);
+        let storage = Arc::clone(&self.storage);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(AUTOSAVE_PERIOD);
+            // the first tick completes immediately
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                let guild_ids = idioms.iter().map(|entry| *entry.key()).collect_vec();
+                for guild_id in guild_ids {
+                    // compacting blocks the guild's messages, so it's only done once enough tokens piled up
+                    if idioms.get(&guild_id).is_some_and(|idiom| idiom.should_compact()) {
+                        if let Some(mut idiom) = idioms.get_mut(&guild_id) {
+                            let freed = idiom.compact();
+                            debug!(target: "wordy", "Guild {}: {} tokens interned, {} freed", guild_id, idiom.interned(), freed);
+                        }
+                    }
+                    if let Err(why) = save_guild(guild_id, &idioms, &recents_emos, &users_emos, &reactions, &externals_emos, &recents_stickers, &last_reads, &storage).await {
+                        warn!(target: "wordy", "Couldn't save Guild {}: {:?}", guild_id, why);
+                    }
+                }
+                trace!(target: "wordy", "Autosaved the guilds");
+            }
+        });
+    }
+
+    pub fn message(&self, cache: &SerenityCache, message: Message) {
+        let guild_id = message.guild_id.unwrap();
+        let config = self.config(guild_id);
+        if !config.should_read_message(&message) 
+            || !config.should_read(&cached_lineage(cache, guild_id, message.channel_id)) {
+            return;
+        }
+        self.record_live(guild_id, message.channel_id, message.id);
         read_message(
-            message.guild_id.unwrap(),
+            guild_id,
             message,
             self.idioms.clone(), 
             self.recents_emos.clone(), 
-            self.servers_emos.clone()
+            self.users_emos.clone(),
+            self.reactions.clone(),
+            self.externals_emos.clone(),
+            self.servers_emos.clone(),
+            self.recents_stickers.clone(),
+            self.servers_stickers.clone(),
+            self.traces.clone(),
+            self.optouts.clone(),
+            &config,
+            false
         );
     }
 
+    /// Moves the last read of the channel, unless its history wasn't read yet, 
+    /// in which case the backfill will be the one to move it and it only needs to know what to skip
+    fn record_live(&self, guild_id: GuildId, channel_id: ChannelId, message_id: MessageId) {
+        let caught_up = match self.catch_ups.get_mut(&guild_id) {
+            Some(mut catch_up) if !catch_up.caught_up(channel_id) => {
+                catch_up.live.entry(channel_id)
+                    .and_modify(|(_, last)| *last = (*last).max(message_id))
+                    .or_insert((message_id, message_id));
+                return;
+            },
+            Some(_) => true,
+            None => false,
+        };
+        if let Some(mut last_read) = self.last_reads.get_mut(&guild_id) {
+            // channels without a last read still have history to read, unless they were just created
+            if caught_up {
+                last_read.entry(channel_id)
+                    .and_modify(|id| *id = (*id).max(message_id))
+                    .or_insert(message_id);
+            } else if let Some(id) = last_read.get_mut(&channel_id) {
+                *id = (*id).max(message_id);
+            }
+        }
+    }
+
+    /// Stops reading the messages of this user, or starts again
+    pub async fn set_optout(&self, user_id: UserId, optout: bool) -> Result<()> {
+        if optout {
+            self.optouts.insert(user_id);
+        } else {
+            self.optouts.remove(&user_id);
+        }
+        self.storage.save_optouts(&self.optouts.iter().map(|user_id| *user_id).collect()).await
+    }
+
+    /// Erases the vocabulary of this user in every guild, 
+    /// and takes the messages of theirs we still remember out of the channels and servers.  
+    /// The guilds are written without it on the next autosave.
+    pub fn forget(&self, user_id: UserId) {
+        let message_ids = self.traces.iter()
+            .filter(|(_, trace)| trace.author_id == user_id)
+            .map(|(message_id, _)| *message_id)
+            .collect_vec();
+        for message_id in message_ids {
+            self.traces.entry(message_id).and_compute_with(|entry| {
+                // the reactions to the messages were made by other people, they stay counted
+                if let Some(entry) = entry {
+                    self.retract_message(entry.value());
+                }
+                Op::Remove
+            });
+        }
+        self.idioms.iter_mut().for_each(|mut idiom| idiom.forget(&user_id));
+        self.users_emos.iter_mut().for_each(|mut user_emos| { user_emos.remove(&user_id); });
+    }
+
+    pub fn config(&self, guild_id: GuildId) -> GuildConfig {
+        self.configs.get(&guild_id).map(|config| config.clone()).unwrap_or_default()
+    }
+
+    pub fn set_config(&self, guild_id: GuildId, config: GuildConfig) -> Result<()> {
+        self.configs.insert(guild_id, config);
+        self.config_store.save(
+            self.configs.iter().map(|entry| (*entry.key(), entry.value().clone())).collect()
+        )
+    }
+
+    /// Cancels the backfill of the guild and forgets its words, so they can be read again with new settings.  
+    /// The emojis, stickers and reactions stay counted, along with the last reads that tell which messages they were counted for.
+    pub fn reset_words(&self, guild_id: GuildId) {
+        if let Some((_, backfill)) = self.backfills.remove(&guild_id) {
+            backfill.abort();
+        }
+        self.idioms.insert(guild_id, Idioms::new(self.config(guild_id).half_life()));
+    }
+
+    /// Forgets the emojis, stickers and reactions of the guild that are older than its retention
+    pub fn prune_emojis(&self, guild_id: GuildId) {
+        let Some(oldest) = self.config(guild_id).emoji_cutoff() else {
+            return;
+        };
+        if let Some(mut recent_emos) = self.recents_emos.get_mut(&guild_id) {
+            recent_emos.prune(oldest);
+        }
+        if let Some(mut user_emos) = self.users_emos.get_mut(&guild_id) {
+            user_emos.values_mut().for_each(|author_emos| author_emos.prune(oldest));
+        }
+        if let Some(mut reactions) = self.reactions.get_mut(&guild_id) {
+            reactions.prune(oldest);
+        }
+        if let Some(mut external_emos) = self.externals_emos.get_mut(&guild_id) {
+            external_emos.prune(oldest);
+        }
+        if let Some(mut recent_stickers) = self.recents_stickers.get_mut(&guild_id) {
+            recent_stickers.prune(oldest);
+        }
+    }
+
+    /// Cancels the backfill of the guild and drops its state.  
+    /// If the guild is only temporarily unavailable we save its state instead, to restore it when it comes back.
+    pub async fn unregister_guild(&self, guild_id: GuildId, unavailable: bool) {
+        if let Some((_, backfill)) = self.backfills.remove(&guild_id) {
+            backfill.abort();
+        }
+        if unavailable {
+            info!(target: "wordy", "Guild {} is unavailable", guild_id);
+            if let Err(why) = save_guild(guild_id, &self.idioms, &self.recents_emos, &self.users_emos, &self.reactions, &self.externals_emos, &self.recents_stickers, &self.last_reads, &self.storage).await {
+                warn!(target: "wordy", "Couldn't save Guild {}: {:?}", guild_id, why);
+            }
+            return;
+        }
+        info!(target: "wordy", "Removed from Guild {}, dropping its state", guild_id);
+        self.catch_ups.remove(&guild_id);
+        self.idioms.remove(&guild_id);
+        self.recents_emos.remove(&guild_id);
+        self.users_emos.remove(&guild_id);
+        self.reactions.remove(&guild_id);
+        self.externals_emos.remove(&guild_id);
+        self.servers_emos.remove(&guild_id);
+        self.recents_stickers.remove(&guild_id);
+        self.servers_stickers.remove(&guild_id);
+        self.last_reads.remove(&guild_id);
+        if let Err(why) = self.storage.delete_guild(guild_id) {
+            warn!(target: "wordy", "Couldn't delete the save of Guild {}: {:?}", guild_id, why);
+        }
+    }
+
+    /// Replaces the contribution of an edited message with its new content.  
+    /// Messages we don't remember are ignored since we can't retract their old content.
+    pub fn message_update(&self, message_id: MessageId, content: String) {
+        self.traces.entry(message_id).and_compute_with(|entry| {
+            let Some(trace) = entry.map(|entry| entry.into_value()) else {
+                return Op::Nop;
+            };
+            if self.optouts.contains(&trace.author_id) {
+                return Op::Remove;
+            }
+            if let (
+                Some(mut idiom),
+                Some(mut recent_emos),
+                Some(mut user_emos),
+                Some(mut external_emos),
+                Some(server_emos)
+            ) = (
+                self.idioms.get_mut(&trace.guild_id),
+                self.recents_emos.get_mut(&trace.guild_id),
+                self.users_emos.get_mut(&trace.guild_id),
+                self.externals_emos.get_mut(&trace.guild_id),
+                self.servers_emos.get(&trace.guild_id)
+            ) {
+                idiom.retract(&trace.channel_id, &trace.author_id, &trace.tokens, trace.timestamp);
+                let author_emos = user_emos.entry(trace.author_id).or_insert_with(TimedCounts::new);
+                trace.emojis.iter().for_each(|emoji_id| {
+                    recent_emos.remove(emoji_id, trace.timestamp);
+                    author_emos.remove(emoji_id, trace.timestamp);
+                });
+                trace.external_emojis.iter().for_each(|emoji| external_emos.remove(emoji, trace.timestamp));
+                let words = words(content, &self.config(trace.guild_id));
+                let emojis = server_emojis(&words, &server_emos);
+                let external_emojis = external_emojis(&words, &server_emos);
+                emojis.iter().for_each(|emoji_id| {
+                    recent_emos.add(*emoji_id, trace.timestamp);
+                    author_emos.add(*emoji_id, trace.timestamp);
+                });
+                external_emojis.iter().for_each(|emoji| external_emos.add(emoji.clone(), trace.timestamp));
+                let tokens = idiom.update(trace.channel_id, trace.author_id, words, trace.timestamp);
+                Op::Put(MessageTrace { tokens, emojis, external_emojis, ..trace })
+            } else {
+                Op::Remove
+            }
+        });
+    }
+
+    pub fn message_delete(&self, message_id: MessageId) {
+        self.traces.entry(message_id).and_compute_with(|entry| {
+            if let Some(entry) = entry {
+                let mut trace = entry.into_value();
+                self.retract_message(&trace);
+                self.retract_reactions(&mut trace, None);
+            }
+            Op::Remove
+        });
+    }
+
+    /// Takes back the words, emojis and stickers of a message, but not its reactions
+    fn retract_message(&self, trace: &MessageTrace) {
+        if let Some(mut idiom) = self.idioms.get_mut(&trace.guild_id) {
+            idiom.retract(&trace.channel_id, &trace.author_id, &trace.tokens, trace.timestamp);
+        }
+        if let Some(mut recent_emos) = self.recents_emos.get_mut(&trace.guild_id) {
+            trace.emojis.iter().for_each(|emoji_id| recent_emos.remove(emoji_id, trace.timestamp));
+        }
+        if let Some(mut user_emos) = self.users_emos.get_mut(&trace.guild_id) {
+            if let Some(author_emos) = user_emos.get_mut(&trace.author_id) {
+                trace.emojis.iter().for_each(|emoji_id| author_emos.remove(emoji_id, trace.timestamp));
+            }
+        }
+        if let Some(mut external_emos) = self.externals_emos.get_mut(&trace.guild_id) {
+            trace.external_emojis.iter().for_each(|emoji| external_emos.remove(emoji, trace.timestamp));
+        }
+        if let Some(mut recent_stickers) = self.recents_stickers.get_mut(&trace.guild_id) {
+            trace.stickers.iter().for_each(|sticker_id| recent_stickers.remove(sticker_id, trace.timestamp));
+        }
+    }
+
+    /// Takes back the reactions counted for the message, only the ones of this emoji if there is one
+    fn retract_reactions(&self, trace: &mut MessageTrace, emoji: Option<&ReactionType>) {
+        let (emoji_id, markup) = match emoji {
+            Some(reaction @ ReactionType::Custom { id, .. }) => (Some(*id), reaction_markup(reaction)),
+            // unicode emojis aren't counted
+            Some(_) => return,
+            None => (None, None),
+        };
+        if let Some(mut reactions) = self.reactions.get_mut(&trace.guild_id) {
+            trace.reactions.retain(|id, count| {
+                let removed = emoji_id.is_none_or(|emoji_id| emoji_id == *id);
+                if removed {
+                    reactions.remove_many(id, *count, trace.timestamp);
+                }
+                !removed
+            });
+        }
+        if let Some(mut external_emos) = self.externals_emos.get_mut(&trace.guild_id) {
+            trace.external_reactions.retain(|emoji, count| {
+                let removed = emoji_id.is_none() || markup.as_ref() == Some(emoji);
+                if removed {
+                    external_emos.remove_many(emoji, *count, trace.timestamp);
+                }
+                !removed
+            });
+        }
+    }
+
+    /// Takes back the reactions of a message that were all removed at once, or only the ones of this emoji.
+    /// The reactions to messages that aren't traced anymore stay counted since we don't know how many there were.
+    pub fn reactions_removed(&self, message_id: MessageId, emoji: Option<&ReactionType>) {
+        self.traces.entry(message_id).and_compute_with(|entry| match entry {
+            Some(entry) => {
+                let mut trace = entry.into_value();
+                self.retract_reactions(&mut trace, emoji);
+                Op::Put(trace)
+            },
+            None => Op::Nop
+        });
+    }
+
+    /// Counts a reaction that was added to a message, or takes it back if it was removed
+    pub fn reaction(&self, cache: &SerenityCache, reaction: &Reaction, added: bool) {
+        let (Some(guild_id), ReactionType::Custom { id: emoji_id, .. }) = (reaction.guild_id, &reaction.emoji) else {
+            return;
+        };
+        if reaction.user_id.is_some_and(|user_id| self.optouts.contains(&user_id)) {
+            return;
+        }
+        let config = self.config(guild_id);
+        if !config.should_read(&cached_lineage(cache, guild_id, reaction.channel_id)) {
+            return;
+        }
+        let timestamp = reaction.message_id.created_at().unix_timestamp();
+        if config.emoji_cutoff().is_some_and(|oldest| timestamp < oldest) {
+            return;
+        }
+        // the guard is released right away, read_message takes the counts before the server emojis
+        let Some(is_server_emoji) = self.servers_emos.get(&guild_id).map(|server_emos| server_emos.contains_key(emoji_id)) else {
+            return;
+        };
+        // the counts and the trace of the message change together, so a deletion takes back exactly what was counted
+        self.traces.entry(reaction.message_id).and_compute_with(|entry| {
+            let mut trace = entry.map(|entry| entry.into_value());
+            if is_server_emoji {
+                if let Some(mut reactions) = self.reactions.get_mut(&guild_id) {
+                    if added {
+                        reactions.add(*emoji_id, timestamp);
+                    } else {
+                        reactions.remove(emoji_id, timestamp);
+                    }
+                }
+                if let Some(trace) = trace.as_mut() {
+                    update_count(&mut trace.reactions, *emoji_id, added);
+                }
+            } else if let (Some(emoji), Some(mut external_emos)) = (reaction_markup(&reaction.emoji), self.externals_emos.get_mut(&guild_id)) {
+                if added {
+                    external_emos.add(emoji.clone(), timestamp);
+                } else {
+                    external_emos.remove(&emoji, timestamp);
+                }
+                if let Some(trace) = trace.as_mut() {
+                    update_count(&mut trace.external_reactions, emoji, added);
+                }
+            }
+            // keep track of what was counted for the message so it can be taken back if the message is deleted
+            trace.map_or(Op::Nop, Op::Put)
+        });
+    }
+
     async fn to_wc_tokens(
         &self, tokens: Vec<(String, f32)>, http: &Arc<Http>
     ) -> Vec<(Token, f32)> {
@@ -139,6 +811,12 @@ impl Wordy {
                     let name = capts.get(1).unwrap().as_str();
                     (Token::Text(name.to_string()), v)
                 }
+            } else if is_emoji(&token) {
+                if let Ok(img) = self.discord_emos.get_unicode(&token).await {
+                    (Token::Img(img), v)
+                } else {
+                    (Token::Text(token), v)
+                }
             } else if let Some(capts) = RE_TAG.captures(&token) {
                 let user_id = capts.get(1).unwrap().as_str().parse().unwrap();
                 if let Ok(member) = http.get_user(user_id).await {
@@ -164,28 +842,107 @@ impl Wordy {
         res
     }
 
-    pub async fn cloud(&self, ctx: &Context, member: &Member) -> RgbaImage {
-        let color = member.colour(&ctx.cache).unwrap_or(Color::from_rgb(255, 255, 255));
-        let member_id = member.user.id;
-        let tokens = self.idioms.get(&member.guild_id).unwrap().idiom(member_id);
-        trace!(target: "wordy", "/cloud: retrieved {} tokens for {}", tokens.len(), member.user.name);
-        let wc_tokens = self.to_wc_tokens(tokens, &ctx.http).await;
-        WordCloud::new()
+    /// The word cloud of the subject, or only its emojis if emojis_only is true
+    pub async fn cloud(&self, ctx: &Context, guild_id: GuildId, subject: &CloudSubject, period: Period, emojis_only: bool) -> Result<RgbaImage> {
+        let scoring = self.config(guild_id).scoring;
+        let role_members = |role: &Role| ctx.cache.guild(guild_id).map(|guild| 
+            guild.members.values()
+                .filter(|member| member.roles.contains(&role.id))
+                .map(|member| member.user.id)
+                .collect_vec()
+        ).unwrap_or_default();
+        let color = match subject {
+            CloudSubject::Member(member) => convert_color(member.colour(&ctx.cache).unwrap_or(Color::from_rgb(255, 255, 255))),
+            CloudSubject::Channel(channel_id, _) => channel_color(*channel_id),
+            // roles without a color have a value of 0
+            CloudSubject::Role(role) => convert_color(if role.colour.0 == 0 { Color::from_rgb(255, 255, 255) } else { role.colour }),
+            CloudSubject::Server => convert_color(Color::BLURPLE),
+        };
+        let wc_tokens = if emojis_only {
+            let start = period.start(Timestamp::now().unix_timestamp());
+            let users = match subject {
+                CloudSubject::Member(member) => Some(vec![member.user.id]),
+                CloudSubject::Role(role) => Some(role_members(role)),
+                CloudSubject::Server => None,
+                CloudSubject::Channel(..) => bail!("Emoji uses aren't counted per channel"),
+            };
+            let tokens = self.emoji_tokens(guild_id, users, start)?;
+            trace!(target: "wordy", "/cloud: retrieved {} emojis for {}", tokens.len(), subject.name());
+            // the emojis whose image couldn't be fetched would show up as text
+            self.to_wc_tokens(tokens, &ctx.http).await.into_iter()
+                .filter(|(token, _)| matches!(token, Token::Img(_)))
+                .collect()
+        } else {
+            let tokens = {
+                let idiom = self.idioms.get(&guild_id).ok_or(anyhow!("Guild is not yet registered"))?;
+                match subject {
+                    CloudSubject::Member(member) => idiom.idiom(member.user.id, period, scoring),
+                    CloudSubject::Channel(channel_id, _) => idiom.place_idiom(*channel_id, period, scoring),
+                    CloudSubject::Role(role) => idiom.group_idiom(&role_members(role), period, scoring),
+                    CloudSubject::Server => idiom.server_idiom(period),
+                }
+            };
+            trace!(target: "wordy", "/cloud: retrieved {} tokens for {}", tokens.len(), subject.name());
+            self.to_wc_tokens(tokens, &ctx.http).await
+        };
+        Ok(WordCloud::new()
         .colors(Colors::BiaisedRainbow { 
-            anchor: convert_color(color),
+            anchor: color,
             variance: 50. 
-        }).generate(wc_tokens)
+        }).generate(wc_tokens))
     }
 
-    pub fn emojis(&self, guild_id: GuildId) -> Result<EmojiRankings> {
+    /// The server emojis used since start, with their uses, in the messages of these users, 
+    /// or in all the messages and reactions if there are no users
+    fn emoji_tokens(&self, guild_id: GuildId, users: Option<Vec<UserId>>, start: Option<i64>) -> Result<Vec<(String, f32)>> {
+        let (Some(recent_emos), Some(user_emos), Some(reactions), Some(server_emos)) = (
+            self.recents_emos.get(&guild_id),
+            self.users_emos.get(&guild_id),
+            self.reactions.get(&guild_id),
+            self.servers_emos.get(&guild_id)
+        ) else {
+            bail!("Guild is not yet registered")
+        };
+        let mut counts: HashMap<EmojiId, u32> = HashMap::new();
+        match users {
+            Some(users) => users.iter()
+                .filter_map(|user_id| user_emos.get(user_id))
+                .flat_map(|emos| emos.counts(start))
+                .for_each(|(emoji_id, count)| *counts.entry(emoji_id).or_default() += count),
+            None => recent_emos.counts(start).into_iter()
+                .chain(reactions.counts(start))
+                .for_each(|(emoji_id, count)| *counts.entry(emoji_id).or_default() += count),
+        }
+        Ok(counts.into_iter()
+            .filter_map(|(emoji_id, count)| Some((server_emos.get(&emoji_id)?.to_string(), count as f32)))
+            .collect())
+    }
+
+    /// Rankings of the server emojis used in the last days, or ever if days is None.  
+    /// If there's a user, only the emojis in their messages are counted.
+    pub fn emojis(&self, guild_id: GuildId, days: Option<i64>, source: EmojiSource, user: Option<UserId>) -> Result<EmojiRankings> {
         if let (
             Some(recent_emos),
+            Some(user_emos),
+            Some(reactions),
             Some(server_emos)
         ) = (
             self.recents_emos.get(&guild_id),
+            self.users_emos.get(&guild_id),
+            self.reactions.get(&guild_id),
             self.servers_emos.get(&guild_id)
         ) {
-            let counts = recent_emos.counts();
+            let start = days.map(|days| Timestamp::now().unix_timestamp() - days * 3600 * 24);
+            let counts = if let Some(user_id) = user {
+                user_emos.get(&user_id).map(|emos| emos.counts(start)).unwrap_or_default()
+            } else {
+                let mut counts = if source == EmojiSource::Reactions { HashMap::new() } else { recent_emos.counts(start) };
+                if source != EmojiSource::Messages {
+                    reactions.counts(start).into_iter()
+                        .for_each(|(emoji_id, count)| *counts.entry(emoji_id).or_default() += count);
+                }
+                counts
+            };
             let mut png_ranking = Vec::new();
             let mut gif_ranking = Vec::new();
             for (emoji_id, emoji) in server_emos.iter() {
@@ -207,4 +964,107 @@ impl Wordy {
             bail!("Guild is not yet registered")
         }
     }
+
+    /// Ranking of the server stickers used in the last days, or ever if days is None
+    pub fn stickers(&self, guild_id: GuildId, days: Option<i64>) -> Result<Vec<StickerUsage>> {
+        if let (
+            Some(recent_stickers),
+            Some(server_stickers)
+        ) = (
+            self.recents_stickers.get(&guild_id),
+            self.servers_stickers.get(&guild_id)
+        ) {
+            let counts = recent_stickers.counts(
+                days.map(|days| Timestamp::now().unix_timestamp() - days * 3600 * 24)
+            );
+            let mut ranking = server_stickers.iter()
+                .map(|(sticker_id, sticker)| (sticker.clone(), *counts.get(sticker_id).unwrap_or(&0)))
+                .collect_vec();
+            ranking.sort_by_key(|(_, count)| *count);
+            ranking.reverse();
+            Ok(norm_emo_ranking(ranking))
+        } else {
+            bail!("Guild is not yet registered")
+        }
+    }
+
+    /// Server emojis unused in the last days or used less than in the days before, 
+    /// and the most used emojis from other servers. Uses in messages and reactions are added up.  
+    /// The days are shortened so that the days before are still within the emoji retention.
+    pub fn emoji_report(&self, guild_id: GuildId, days: i64) -> Result<EmojiReport> {
+        let retention = self.config(guild_id).emoji_retention as i64;
+        let requested_days = days;
+        let days = if retention > 0 { days.min((retention / 2).max(1)) } else { days };
+        // only possible with a retention of a single day
+        let comparable = retention == 0 || 2*days <= retention;
+        if let (
+            Some(recent_emos),
+            Some(reactions),
+            Some(external_emos),
+            Some(server_emos)
+        ) = (
+            self.recents_emos.get(&guild_id),
+            self.reactions.get(&guild_id),
+            self.externals_emos.get(&guild_id),
+            self.servers_emos.get(&guild_id)
+        ) {
+            let now = Timestamp::now().unix_timestamp();
+            let start = now - days * 3600 * 24;
+            let previous_start = start - days * 3600 * 24;
+            let counts_between = |start: i64, end: i64| {
+                let mut counts = recent_emos.counts_between(start, end);
+                reactions.counts_between(start, end).into_iter()
+                    .for_each(|(emoji_id, count)| *counts.entry(emoji_id).or_default() += count);
+                counts
+            };
+            let uses = counts_between(start, i64::MAX);
+            let previous = counts_between(previous_start, start);
+            let entries = server_emos.values().map(|emoji| ReportEntry {
+                name: emoji.name.clone(),
+                id: emoji.id,
+                animated: emoji.animated,
+                uses: *uses.get(&emoji.id).unwrap_or(&0),
+                previous_uses: *previous.get(&emoji.id).unwrap_or(&0),
+            });
+            let (unused, used): (Vec<_>, Vec<_>) = entries.partition(|entry| entry.uses == 0);
+            // the ones that used to be popular first
+            let unused = unused.into_iter()
+                .sorted_by_key(|entry| Reverse(entry.previous_uses))
+                .collect_vec();
+            // the steepest drops first
+            let declining = used.into_iter()
+                .filter(|entry| comparable && entry.uses < entry.previous_uses)
+                .sorted_by_key(|entry| entry.uses as i64 - entry.previous_uses as i64)
+                .collect_vec();
+            let external_previous = external_emos.counts_between(previous_start, start);
+            let external = external_emos.counts(Some(start)).into_iter()
+                .filter_map(|(emoji, uses)| {
+                    let caps = RE_EMO.captures(&emoji)?;
+                    let id = EmojiId::new(caps.get(2).unwrap().as_str().parse::<u64>().unwrap());
+                    // it may have been added to the server since
+                    (!server_emos.contains_key(&id)).then(|| ReportEntry {
+                        name: caps.get(1).unwrap().as_str().to_string(),
+                        id,
+                        animated: emoji.starts_with("<a:"),
+                        uses,
+                        previous_uses: *external_previous.get(&emoji).unwrap_or(&0),
+                    })
+                })
+                .sorted_by_key(|entry| Reverse(entry.uses))
+                .collect_vec();
+            Ok(EmojiReport { days, requested_days, retention, unused, declining, external })
+        } else {
+            bail!("Guild is not yet registered")
+        }
+    }
+
+    /// The users who put this emoji in their messages the most in the last days, or ever if days is None
+    pub fn emoji_users(&self, guild_id: GuildId, emoji_id: EmojiId, days: Option<i64>) -> Result<Vec<(UserId, u32)>> {
+        let user_emos = self.users_emos.get(&guild_id).ok_or(anyhow!("Guild is not yet registered"))?;
+        let start = days.map(|days| Timestamp::now().unix_timestamp() - days * 3600 * 24);
+        Ok(user_emos.iter()
+            .filter_map(|(user_id, emos)| Some((*user_id, *emos.counts(start).get(&emoji_id)?)))
+            .sorted_by_key(|(_, count)| Reverse(*count))
+            .collect())
+    }
 }
@@ -1,14 +1,40 @@
-use std::{io::{Cursor, Seek, SeekFrom}, sync::Arc};
+use std::{collections::HashMap, io::{Cursor, Seek, SeekFrom}, sync::Arc};
+use dashmap::mapref::entry::Entry;
 use log::{info, trace, warn};
 use image::{write_buffer_with_format, ColorType, ImageFormat};
 use anyhow::{Result, bail, anyhow};
-use serenity::{all::{CommandInteraction, CreateCommand}, http::Http, model::{
-    prelude::{Guild, GuildId}, 
-    Timestamp
+use serenity::{all::{CommandInteraction, CommandOptionType, CreateCommand, CreateCommandOption, ResolvedOption, ResolvedValue}, http::Http, model::{
+    prelude::{ChannelId, EmojiIdentifier, Guild, GuildId, Mentionable, WebhookId}, Permissions
 }, prelude::Context};
-use crate::{wordy::{Wordy, register_guild, read_message}, discord_util::{read_past, Bot, Attachment}, emoji_usage::emo_ranking_msg};
-const READ_PAST: u64 = 10_000;
-const DAYS: i64 = 100;
+use crate::{wordy::{Wordy, CloudSubject, CatchUp, register_guild, read_message, save_guild}, discord_util::{read_past, read_after, read_threads, is_readable, lineage, Bot, Attachment}, emoji_usage::{emo_ranking_msg, sticker_ranking_msg, user_ranking_msg, report_msg, report_csv, EmojiSource}, config::toggle, idiom::{UrlRule, Stemmer, Period, Scoring}};
+
+// Discord didn't exist 100 years ago, so it's as good as reading everything
+const MAX_DAYS: u64 = 36_500;
+// the period of /emojis report when it's not given
+const REPORT_DAYS: i64 = 30;
+// how many users /emojis emoji shows
+const TOP_USERS: usize = 10;
+
+fn period_option(description: &str) -> CreateCommandOption {
+    Period::ALL.into_iter().fold(
+        CreateCommandOption::new(CommandOptionType::String, "period", description),
+        |option, period| option.add_string_choice(period.name(), period.name())
+    )
+}
+
+/// A period counted in days, for the emoji and sticker rankings
+fn days_option(description: &str) -> CreateCommandOption {
+    CreateCommandOption::new(CommandOptionType::String, "period", description)
+        .add_string_choice("Last 7 days", "7d")
+        .add_string_choice("Last 30 days", "30d")
+        .add_string_choice("Last 90 days", "90d")
+        .add_string_choice("All time", "all")
+}
+
+/// The number of days of a days_option, None for all time
+fn parse_days(name: &str) -> Option<i64> {
+    name.strip_suffix('d').and_then(|days| days.parse().ok())
+}
 
 impl Wordy {
     pub async fn cloud_command(
@@ -16,14 +42,45 @@ impl Wordy {
         ctx: Context,
         command: CommandInteraction,
     ) -> Result<()> {
-        if command.guild_id.is_none() {
+        let Some(guild_id) = command.guild_id else {
             bail!("Command wasn't invoked in a Guild.");
+        };
+        let mut subjects = Vec::new();
+        let mut period = Period::All;
+        let mut emojis_only = false;
+        for option in command.data.options() {
+            match (option.name, option.value) {
+                ("user", ResolvedValue::User(user, _)) => match guild_id.member(&ctx.http, user.id).await {
+                    Ok(member) => subjects.push(CloudSubject::Member(Box::new(member))),
+                    // the user may have left the server
+                    Err(why) => {
+                        warn!(target: "wordy", "Couldn't get member {}: {}", user.id, why);
+                        return ctx.http.whisper(&command, &format!("Couldn't find {} in this server.", user.name)).await;
+                    }
+                },
+                ("channel", ResolvedValue::Channel(channel)) => subjects.push(
+                    CloudSubject::Channel(channel.id, channel.name.clone().unwrap_or_default())
+                ),
+                ("role", ResolvedValue::Role(role)) => subjects.push(CloudSubject::Role(role.clone())),
+                ("server", ResolvedValue::Boolean(true)) => subjects.push(CloudSubject::Server),
+                ("period", ResolvedValue::String(name)) => period = Period::from_name(name).unwrap_or(Period::All),
+                ("emojis", ResolvedValue::Boolean(value)) => emojis_only = value,
+                _ => {}
+            }
         }
-        let member = command
-            .member
-            .as_ref()
-            .ok_or(anyhow!("Couldn't get member."))?;
-        let image = self.cloud(&ctx, &member).await;
+        if subjects.len() > 1 {
+            return ctx.http.whisper(&command, "Please pick only one of user, channel, role or server.").await;
+        }
+        if emojis_only && subjects.iter().any(|subject| matches!(subject, CloudSubject::Channel(..))) {
+            return ctx.http.whisper(&command, "Emoji uses aren't counted per channel, please pick a user, role or server.").await;
+        }
+        let subject = match subjects.pop() {
+            Some(subject) => subject,
+            None => CloudSubject::Member(
+                command.member.clone().ok_or(anyhow!("Couldn't get member."))?
+            )
+        };
+        let image = self.cloud(&ctx, guild_id, &subject, period, emojis_only).await?;
         let mut img_file = Cursor::new(Vec::new());
         write_buffer_with_format(
             &mut img_file,
@@ -42,7 +99,7 @@ impl Wordy {
                 "",
                 vec![Attachment {
                     file: img_vec,
-                    filename: format!("WordCloud_{}.png", member.display_name()),
+                    filename: format!("WordCloud_{}.png", subject.name()),
                 }],
             )
             .await
@@ -57,14 +114,237 @@ impl Wordy {
             .guild_id
             .as_ref()
             .ok_or(anyhow!("Couldn't get member."))?;
-        let emoji_rankings = self.emojis(*guild_id)?;
-        let png_msg = "Static emoji ranking:\n".to_string() + &emo_ranking_msg(emoji_rankings.png);
+        let mut days = None;
+        let mut source = EmojiSource::Both;
+        let mut report = false;
+        let mut all_time = false;
+        let mut user = None;
+        let mut emoji = None;
+        for option in command.data.options() {
+            match (option.name, option.value) {
+                ("user", ResolvedValue::User(value, _)) => user = Some(value.clone()),
+                ("emoji", ResolvedValue::String(value)) => emoji = Some(value.trim().to_string()),
+                ("period", ResolvedValue::String(name)) => {
+                    days = parse_days(name);
+                    all_time = days.is_none();
+                },
+                ("source", ResolvedValue::String(name)) => source = EmojiSource::from_name(name).unwrap_or(EmojiSource::Both),
+                ("report", ResolvedValue::Boolean(value)) => report = value,
+                _ => {}
+            }
+        }
+        if report {
+            if !command.member.as_ref().and_then(|member| member.permissions).is_some_and(|perms| perms.manage_guild()) {
+                return ctx.http.whisper(&command, "You need the Manage Server permission to see the report.").await;
+            }
+            if all_time {
+                return ctx.http.whisper(&command, "The report compares a period with the one before, please pick 7, 30 or 90 days.").await;
+            }
+            let report = self.emoji_report(*guild_id, days.unwrap_or(REPORT_DAYS))?;
+            return ctx.http.answer(&command, &report_msg(&report), vec![Attachment {
+                file: report_csv(&report).into_bytes(),
+                filename: "emoji_report.csv".to_string(),
+            }]).await;
+        }
+        if user.is_some() && source == EmojiSource::Reactions {
+            return ctx.http.whisper(&command, "Reactions aren't counted per user, only the emojis in their messages are.").await;
+        }
+        if let Some(emoji) = emoji {
+            let Some(emoji) = emoji.parse::<EmojiIdentifier>().ok()
+                .filter(|emoji| self.servers_emos.get(guild_id).is_some_and(|server_emos| server_emos.contains_key(&emoji.id))) else {
+                return ctx.http.whisper(&command, "Please pick an emoji of this server.").await;
+            };
+            let users = self.emoji_users(*guild_id, emoji.id, days)?;
+            let total = users.iter().map(|(_, count)| count).sum();
+            // mentions show the names without having to fetch the users, and answers don't ping anyone
+            let names = users.into_iter().take(TOP_USERS)
+                .map(|(user_id, count)| (user_id.mention().to_string(), count))
+                .collect();
+            let msg = format!("Top users of {}:\n", emoji) + &user_ranking_msg(names, total);
+            return ctx.http.answer(&command, &msg, vec![]).await;
+        }
+        let emoji_rankings = self.emojis(*guild_id, days, source, user.as_ref().map(|user| user.id))?;
+        let title = user.as_ref().map_or(String::new(), |user| format!("Emojis of {}\n", user.name));
+        let png_msg = title + "Static emoji ranking:\n" + &emo_ranking_msg(emoji_rankings.png);
         let gif_msg =
             "Animated emoji ranking:\n".to_string() + &emo_ranking_msg(emoji_rankings.gif);
         ctx.http.answer(&command, &png_msg, vec![]).await?;
         ctx.http.followup(&command, &gif_msg, vec![]).await
     }
 
+    pub async fn stickers_command(
+        &self,
+        ctx: Context,
+        command: CommandInteraction,
+    ) -> Result<()> {
+        let guild_id = command
+            .guild_id
+            .as_ref()
+            .ok_or(anyhow!("Couldn't get member."))?;
+        let days = command.data.options().into_iter()
+            .find_map(|option| match (option.name, option.value) {
+                ("period", ResolvedValue::String(name)) => parse_days(name),
+                _ => None
+            });
+        let msg = "Sticker ranking:\n".to_string() + &sticker_ranking_msg(self.stickers(*guild_id, days)?);
+        ctx.http.answer(&command, &msg, vec![]).await
+    }
+
+    pub async fn privacy_command(
+        &self,
+        ctx: Context,
+        command: CommandInteraction,
+    ) -> Result<()> {
+        let user_id = command.user.id;
+        let options = command.data.options();
+        let subcommand = options.first().ok_or(anyhow!("Missing subcommand."))?;
+        let msg = match subcommand.name {
+            "optout" => {
+                self.set_optout(user_id, true).await?;
+                "Your messages won't be read anymore. Use `/privacy forget` to also erase your word cloud."
+            },
+            "optin" => {
+                self.set_optout(user_id, false).await?;
+                "Your messages will be read again."
+            },
+            "forget" => {
+                self.forget(user_id);
+                if self.optouts.contains(&user_id) {
+                    "Your word cloud has been erased, and your recent messages were taken out of the server and channel clouds."
+                } else {
+                    "Your word cloud has been erased, and your recent messages were taken out of the server and channel clouds. Use `/privacy optout` to stop the bot from reading your new messages."
+                }
+            },
+            _ => bail!("Unknown subcommand")
+        };
+        ctx.http.whisper(&command, msg).await
+    }
+
+    pub async fn wordy_command(
+        &self,
+        ctx: Context,
+        command: CommandInteraction,
+    ) -> Result<()> {
+        let Some(guild_id) = command.guild_id else {
+            bail!("Command wasn't invoked in a Guild.");
+        };
+        // the command is hidden from non admins by default, but server owners can override that
+        if !command.member.as_ref().and_then(|member| member.permissions).is_some_and(|perms| perms.manage_guild()) {
+            return ctx.http.whisper(&command, "You need the Manage Server permission to use this command.").await;
+        }
+        let options = command.data.options();
+        let Some(ResolvedOption { name: subcommand, value: ResolvedValue::SubCommand(options), .. }) = options.first() else {
+            bail!("Missing subcommand.");
+        };
+        let mut config = self.config(guild_id);
+        // the words are read again when the way they are read changes, 
+        // the channels that became readable are read when the channels change
+        let mut reread = false;
+        let mut catch_up = false;
+        let mut prune = false;
+        for option in options {
+            match (*subcommand, option.name, &option.value) {
+                ("config", "messages", ResolvedValue::Integer(messages)) => {
+                    config.read_past = *messages as u64;
+                    reread = true;
+                },
+                ("config", "days", ResolvedValue::Integer(days)) => {
+                    config.days = *days;
+                    reread = true;
+                },
+                ("config", "half_life", ResolvedValue::Integer(days)) => match u32::try_from(*days) {
+                    Ok(days) => {
+                        config.half_life = days;
+                        reread = true;
+                    },
+                    Err(_) => return ctx.http.whisper(&command, &format!("The half life must be between 0 and {} days.", MAX_DAYS)).await
+                },
+                ("config", "scoring", ResolvedValue::String(name)) => if let Some(scoring) = Scoring::from_name(name) {
+                    config.scoring = scoring;
+                },
+                ("config", "emoji_retention", ResolvedValue::Integer(days)) => match u32::try_from(*days) {
+                    Ok(days) => {
+                        config.emoji_retention = days;
+                        prune = true;
+                    },
+                    Err(_) => return ctx.http.whisper(&command, &format!("The emoji retention must be between 0 and {} days.", MAX_DAYS)).await
+                },
+                ("channels", "allow", ResolvedValue::Channel(channel)) => {
+                    config.deny_channels.remove(&channel.id);
+                    toggle(&mut config.allow_channels, channel.id);
+                    catch_up = true;
+                },
+                ("channels", "deny", ResolvedValue::Channel(channel)) => {
+                    config.allow_channels.remove(&channel.id);
+                    toggle(&mut config.deny_channels, channel.id);
+                    catch_up = true;
+                },
+                ("channels", "clear", ResolvedValue::Boolean(true)) => {
+                    config.allow_channels.clear();
+                    config.deny_channels.clear();
+                    catch_up = true;
+                },
+                ("bots", "bot", ResolvedValue::User(user, _)) => toggle(&mut config.allow_bots, user.id),
+                ("bots", "webhook", ResolvedValue::String(id)) => match id.trim().parse::<u64>() {
+                    Ok(id) if id > 0 => toggle(&mut config.allow_webhooks, WebhookId::new(id)),
+                    _ => return ctx.http.whisper(&command, "Webhook ids are the number in the webhook's URL.").await
+                },
+                ("stopwords", "words", ResolvedValue::String(words)) => {
+                    words
+                        .split(|c: char| c == ',' || c.is_whitespace())
+                        .filter(|word| !word.is_empty())
+                        .for_each(|word| toggle(&mut config.stopwords, word.to_lowercase()));
+                    reread = true;
+                },
+                ("markdown", name, value) => {
+                    match (name, value) {
+                        ("links", ResolvedValue::String(links)) => config.markdown.urls = match *links {
+                            "keep" => UrlRule::Keep,
+                            "drop" => UrlRule::Drop,
+                            _ => UrlRule::Domain
+                        },
+                        ("code", ResolvedValue::Boolean(skip)) => config.markdown.skip_code = *skip,
+                        ("quotes", ResolvedValue::Boolean(skip)) => config.markdown.skip_quotes = *skip,
+                        ("spoilers", ResolvedValue::Boolean(skip)) => config.markdown.skip_spoilers = *skip,
+                        ("repeats", ResolvedValue::Boolean(collapse)) => config.collapse_repeats = *collapse,
+                        _ => {}
+                    }
+                    reread = true;
+                },
+                ("stemming", "language", ResolvedValue::String(language)) => {
+                    config.stemmer = Stemmer::ALL
+                        .into_iter()
+                        .find(|stemmer| stemmer.name() == *language);
+                    reread = true;
+                },
+                _ => {}
+            }
+        }
+        if options.is_empty() {
+            return ctx.http.whisper(&command, &config.msg()).await;
+        }
+        let msg = config.msg();
+        self.set_config(guild_id, config)?;
+        if prune {
+            self.prune_emojis(guild_id);
+        }
+        // scores are computed when making a cloud, and new bots are only read from now on
+        if !reread && !catch_up {
+            return ctx.http.whisper(&command, &msg).await;
+        }
+        let guild = ctx.cache.guild(guild_id)
+            .map(|guild| guild.clone())
+            .ok_or(anyhow!("Guild is not in the cache."))?;
+        if reread {
+            ctx.http.whisper(&command, &format!("{}\n\nReading the past messages again with these settings.", msg)).await?;
+            self.reset_words(guild_id);
+        } else {
+            ctx.http.whisper(&command, &format!("{}\n\nReading the channels that weren't read yet.", msg)).await?;
+        }
+        self.register_guild(ctx.http.clone(), guild, reread).await;
+        Ok(())
+    }
+
     pub async fn info_command(
         &self,
         ctx: Context,
@@ -83,45 +363,247 @@ impl Wordy {
     pub async fn register_commands(&self, http: Arc<Http>, guild_id: GuildId) {
         trace!(target: "wordy", "Registering slash commands for Guild {}", guild_id);
         if let Err(why) = GuildId::set_commands(guild_id, http, vec![
-            CreateCommand::new("cloud").description("Discover the word cloud that defines you!"),
-            CreateCommand::new("emojis").description("Recent emoji usage stats."),
+            CreateCommand::new("cloud").description("Discover the word cloud that defines you!")
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::User, "user", "Discover someone else's word cloud instead"
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Channel, "channel", "Discover the word cloud of a channel instead"
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Role, "role", "Discover the word cloud of a role instead"
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean, "server", "Discover the word cloud of the whole server instead"
+                ))
+                .add_option(period_option("Only look at the messages of this period"))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean, "emojis", "Only show the emojis"
+                )),
+            CreateCommand::new("emojis").description("Recent emoji usage stats.")
+                .add_option(days_option("Only count the emojis used in this period"))
+                .add_option(EmojiSource::ALL.into_iter().fold(
+                    CreateCommandOption::new(CommandOptionType::String, "source", "Count the emojis in messages, in reactions, or both"),
+                    |option, source| option.add_string_choice(source.name(), source.name())
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::User, "user", "Rank the emojis in someone's messages instead"
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::String, "emoji", "Rank who puts this emoji in their messages the most instead"
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::Boolean, "report", "Admins only: unused, declining and external emojis, with a CSV export"
+                )),
+            CreateCommand::new("stickers").description("Recent sticker usage stats.")
+                .add_option(days_option("Only count the stickers used in this period")),
+            CreateCommand::new("privacy").description("Control what the bot knows about you.")
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "optout", "Stop the bot from reading your messages"
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "optin", "Let the bot read your messages again"
+                ))
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "forget", "Erase your word cloud everywhere"
+                )),
+            CreateCommand::new("wordy").description("Configure the bot for this server.")
+                .default_member_permissions(Permissions::MANAGE_GUILD)
+                .dm_permission(false)
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "config", "Show or change how past messages are read"
+                )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Integer, "messages", "How many past messages to read in each channel"
+                    ).min_int_value(0))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Integer, "days", "Don't read past messages older than this many days"
+                    ).min_int_value(1).max_int_value(MAX_DAYS))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Integer, "half_life", "Messages lose half their weight every this many days, 0 to never"
+                    ).min_int_value(0).max_int_value(MAX_DAYS))
+                    .add_sub_option(Scoring::ALL.into_iter().fold(
+                        CreateCommandOption::new(CommandOptionType::String, "scoring", "How the words of clouds are weighted"),
+                        |option, scoring| option.add_string_choice(scoring.name(), scoring.name())
+                    ))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Integer, "emoji_retention", "Forget emoji uses after this many days, 0 to never"
+                    ).min_int_value(0).max_int_value(MAX_DAYS))
+                )
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "channels", "Show or change which channels and categories are read"
+                )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Channel, "allow", "Only read this channel or category (and the other allowed ones), or stop doing so"
+                    ))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Channel, "deny", "Never read this channel or category, or start reading it again"
+                    ))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Boolean, "clear", "Read every channel again"
+                    ))
+                )
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "bots", "Show or change which bots and webhooks are read"
+                )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::User, "bot", "Read the messages of this bot, or stop doing so"
+                    ))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::String, "webhook", "Read the messages of the webhook with this id, or stop doing so"
+                    ))
+                )
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "stopwords", "Show or change the words to ignore"
+                )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::String, "words", "Words to ignore (or stop ignoring), separated by spaces"
+                    ))
+                )
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "markdown", "Show or change how links, code, quotes and spoilers are read"
+                )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::String, "links", "What to do with links"
+                    )
+                        .add_string_choice("Keep them", "keep")
+                        .add_string_choice("Only keep the domain", "domain")
+                        .add_string_choice("Ignore them", "drop")
+                    )
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Boolean, "code", "Ignore code blocks"
+                    ))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Boolean, "quotes", "Ignore quotes"
+                    ))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Boolean, "spoilers", "Ignore spoilers"
+                    ))
+                    .add_sub_option(CreateCommandOption::new(
+                        CommandOptionType::Boolean, "repeats", "Shorten letters repeated 3 times or more, \"nooooo\" becomes \"noo\""
+                    ))
+                )
+                .add_option(CreateCommandOption::new(
+                    CommandOptionType::SubCommand, "stemming", "Show or change whether forms of a word (\"run\", \"running\") are merged"
+                )
+                    .add_sub_option(Stemmer::ALL.into_iter().fold(
+                        CreateCommandOption::new(CommandOptionType::String, "language", "Language of the server")
+                            .add_string_choice("Don't merge word forms", "off"),
+                        |option, stemmer| option.add_string_choice(stemmer.name(), stemmer.name())
+                    ))
+                ),
             CreateCommand::new("info").description("Information about this bot.")
         ]).await {
             warn!(target: "wordy", "Couldn't register slash commmands: {}", why);
         };
     }
 
-    pub async fn register_guild(&self, http: Arc<Http>, guild: Guild) {
-        // only read messages that are less than 100 days old
-        let cutoff_date =
-            Timestamp::from_unix_timestamp(Timestamp::now().unix_timestamp() - 3600 * 24 * DAYS)
-                .unwrap();
+    /// Registers the guild and reads the messages we missed.  
+    /// With reread, the history is read again for its words only, except the messages that were never counted.
+    pub async fn register_guild(&self, http: Arc<Http>, guild: Guild, reread: bool) {
+        let config = self.config(guild.id);
+        let cutoff_date = config.cutoff_date();
         if let Ok(channels) = guild.channels(&http).await {
-            if !register_guild(
+            // on reconnects the guild is already registered, but we still need to catch up
+            register_guild(
                 &guild,
                 self.idioms.clone(),
                 self.recents_emos.clone(),
+                self.users_emos.clone(),
+                self.reactions.clone(),
+                self.externals_emos.clone(),
                 self.servers_emos.clone(),
-            ) {
-                return;
-            }
+                self.recents_stickers.clone(),
+                self.servers_stickers.clone(),
+                self.last_reads.clone(),
+                &self.storage,
+                config.half_life(),
+            );
+            let backfill = match self.backfills.entry(guild.id) {
+                Entry::Occupied(entry) if !entry.get().is_finished() => {
+                    info!(target: "wordy", "A backfill is already running for {} (id {})", guild.name, guild.id);
+                    return;
+                },
+                entry => entry
+            };
+            // live messages only move the last reads of the channels the backfill is done with
+            let live = self.catch_ups.remove(&guild.id).map(|(_, catch_up)| catch_up.live).unwrap_or_default();
+            self.catch_ups.insert(guild.id, CatchUp::new(live));
+            let resume_from = self.last_reads.get(&guild.id)
+                .map(|last_read| last_read.clone())
+                .unwrap_or_default();
             let http = Arc::clone(&http);
             let idioms = Arc::clone(&self.idioms);
             let recents_emos = Arc::clone(&self.recents_emos);
+            let users_emos = Arc::clone(&self.users_emos);
+            let reactions = Arc::clone(&self.reactions);
+            let externals_emos = Arc::clone(&self.externals_emos);
             let servers_emos = Arc::clone(&self.servers_emos);
-            tokio::spawn(async move {
-                for (_channel_id, channel) in channels {
-                    let read_result = read_past(&http, &channel, READ_PAST, cutoff_date).await;
+            let recents_stickers = Arc::clone(&self.recents_stickers);
+            let servers_stickers = Arc::clone(&self.servers_stickers);
+            let last_reads = Arc::clone(&self.last_reads);
+            let catch_ups = Arc::clone(&self.catch_ups);
+            let storage = Arc::clone(&self.storage);
+            let traces = self.traces.clone();
+            let optouts = Arc::clone(&self.optouts);
+            let handle = tokio::spawn(async move {
+                let threads = read_threads(&http, guild.id, channels.values(), cutoff_date).await;
+                let parents: HashMap<ChannelId, ChannelId> = channels.values().chain(&threads)
+                    .filter_map(|channel| Some((channel.id, channel.parent_id?)))
+                    .collect();
+                let channels = channels.into_values().chain(threads)
+                    .filter(|channel| 
+                        is_readable(channel) 
+                        && config.should_read(&lineage(channel.id, |id| parents.get(&id).copied()))
+                    );
+                for channel in channels {
+                    // if we already read this channel before, only read the messages we missed
+                    let last_read = resume_from.get(&channel.id).copied();
+                    let read_result = match last_read {
+                        Some(last_read) if !reread => read_after(&http, &channel, last_read, config.read_past, cutoff_date).await,
+                        _ => read_past(&http, &channel, config.read_past, cutoff_date).await
+                    };
                     let len = read_result.messages.len();
-                    for message in read_result.messages {
+                    let newest = read_result.messages.iter().map(|message| message.id).max();
+                    // the messages we received live since the backfill started were already counted
+                    let first_live = catch_ups.get(&guild.id)
+                        .and_then(|catch_up| catch_up.live.get(&channel.id).map(|(first, _)| *first));
+                    let messages = read_result.messages.into_iter().filter(|message| 
+                        first_live.is_none_or(|first| message.id < first) 
+                        && (reread || !traces.contains_key(&message.id))
+                        && config.should_read_message(message)
+                    );
+                    for message in messages {
+                        // the emojis of the messages up to the last read were already counted
+                        let words_only = reread && last_read.is_some_and(|last_read| message.id <= last_read);
                         read_message(
                             guild.id,
                             message,
                             idioms.clone(),
                             recents_emos.clone(),
+                            users_emos.clone(),
+                            reactions.clone(),
+                            externals_emos.clone(),
                             servers_emos.clone(),
+                            recents_stickers.clone(),
+                            servers_stickers.clone(),
+                            traces.clone(),
+                            optouts.clone(),
+                            &config,
+                            words_only,
                         );
                     }
+                    // the history of the channel was read if the read went through, 
+                    // or if it started from the last read since it goes forward in time
+                    let caught_up = read_result.result.is_ok() || last_read.is_some();
+                    if let (true, Some(mut catch_up)) = (caught_up, catch_ups.get_mut(&guild.id)) {
+                        let last_live = catch_up.live.remove(&channel.id).map(|(_, last)| last);
+                        catch_up.done.insert(channel.id);
+                        let last_read = [newest, last_live, last_read].into_iter().flatten().max();
+                        if let (Some(last_read), Some(mut last_reads)) = (last_read, last_reads.get_mut(&guild.id)) {
+                            last_reads.insert(channel.id, last_read);
+                        }
+                    }
                     if let Err(why) = read_result.result {
                         if len == 0 {
                             warn!(
@@ -135,10 +617,14 @@ impl Wordy {
                             );
                         }
                     } else if len > 0 {
-                        info!(target: "wordy", "Read {} past messages in {}/{}", len, guild.name, channel.name())
+                        info!(target: "wordy", "Read {} past messages in {}/{}", len, guild.name, channel.name());
                     }
                 }
+                if let Err(why) = save_guild(guild.id, &idioms, &recents_emos, &users_emos, &reactions, &externals_emos, &recents_stickers, &last_reads, &storage).await {
+                    warn!(target: "wordy", "Couldn't save {}: {:?}", guild.name, why);
+                }
             });
+            backfill.insert(handle.abort_handle());
         }
     }
 }
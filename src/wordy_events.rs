@@ -3,7 +3,7 @@ use anyhow::anyhow;
 use serenity::{
     all::Interaction, async_trait, model:: {
         gateway::Ready,
-        guild::Guild, prelude::{Emoji, EmojiId, GuildId, Message},
+        event::MessageUpdateEvent, guild::{Guild, UnavailableGuild}, prelude::{ChannelId, Emoji, EmojiId, GuildId, Message, MessageId, Reaction, Sticker, StickerId},
     }, prelude::*
 };
 use log::{info, trace, warn};
@@ -19,6 +19,9 @@ impl EventHandler for Wordy {
                 if let Err(why) = match command_name.as_str() {
                     "cloud" => self.cloud_command(ctx, command).await,
                     "emojis" => self.emojis_command(ctx, command).await,
+                    "stickers" => self.stickers_command(ctx, command).await,
+                    "privacy" => self.privacy_command(ctx, command).await,
+                    "wordy" => self.wordy_command(ctx, command).await,
                     "info" => self.info_command(ctx, command).await,
                     _ => Err(anyhow!("Unknown command"))
                 } {
@@ -35,16 +38,76 @@ impl EventHandler for Wordy {
 
     async fn guild_create(&self, ctx: Context, guild: Guild, _is_new: Option<bool>) {
         self.register_commands(ctx.http.clone(), guild.id).await;
-        self.register_guild(ctx.http, guild).await;
+        self.register_guild(ctx.http, guild, false).await;
     }
 
-    async fn message(&self, _ctx: Context, message: Message) {
+    async fn guild_delete(&self, _ctx: Context, incomplete: UnavailableGuild, _full: Option<Guild>) {
+        // unavailable is false if the bot was kicked, or if the guild was deleted
+        self.unregister_guild(incomplete.id, incomplete.unavailable).await;
+    }
+
+    async fn message(&self, ctx: Context, message: Message) {
         if message.guild_id.is_some() {
             trace!(target: "wordy", "Read a new message from {}", message.author.name);
-            self.message(message);
+            self.message(&ctx.cache, message);
         }
     }
 
+    async fn message_update(
+        &self,
+        _ctx: Context,
+        _old_if_available: Option<Message>,
+        _new: Option<Message>,
+        event: MessageUpdateEvent,
+    ) {
+        // content is None if only the embeds were updated
+        if let (Some(_), Some(content)) = (event.guild_id, event.content) {
+            trace!(target: "wordy", "Read an edited message");
+            self.message_update(event.id, content);
+        }
+    }
+
+    async fn message_delete(
+        &self,
+        _ctx: Context,
+        _channel_id: ChannelId,
+        deleted_message_id: MessageId,
+        _guild_id: Option<GuildId>,
+    ) {
+        self.message_delete(deleted_message_id);
+    }
+
+    async fn message_delete_bulk(
+        &self,
+        _ctx: Context,
+        _channel_id: ChannelId,
+        multiple_deleted_messages_ids: Vec<MessageId>,
+        _guild_id: Option<GuildId>,
+    ) {
+        multiple_deleted_messages_ids.into_iter().for_each(|message_id| self.message_delete(message_id));
+    }
+
+    async fn reaction_add(&self, ctx: Context, add_reaction: Reaction) {
+        self.reaction(&ctx.cache, &add_reaction, true);
+    }
+
+    async fn reaction_remove(&self, ctx: Context, removed_reaction: Reaction) {
+        self.reaction(&ctx.cache, &removed_reaction, false);
+    }
+
+    async fn reaction_remove_all(
+        &self,
+        _ctx: Context,
+        _channel_id: ChannelId,
+        removed_from_message_id: MessageId,
+    ) {
+        self.reactions_removed(removed_from_message_id, None);
+    }
+
+    async fn reaction_remove_emoji(&self, _ctx: Context, removed_reactions: Reaction) {
+        self.reactions_removed(removed_reactions.message_id, Some(&removed_reactions.emoji));
+    }
+
     async fn guild_emojis_update(
         &self,
         _ctx: Context,
@@ -53,4 +116,13 @@ impl EventHandler for Wordy {
     ) {
         self.servers_emos.insert(guild_id, current_state.clone());
     }
+
+    async fn guild_stickers_update(
+        &self,
+        _ctx: Context,
+        guild_id: GuildId,
+        current_state: HashMap<StickerId, Sticker>,
+    ) {
+        self.servers_stickers.insert(guild_id, current_state);
+    }
 }